@@ -0,0 +1,119 @@
+//! An expectimax player: max nodes try the four slides, chance nodes average
+//! over every empty cell receiving a 2 or a 4, and the leaves are scored by a
+//! heuristic over the log2 tile values.
+
+use game_rs::twenty48::{self, Board, Direction, DIRECTIONS};
+
+const EMPTY_WEIGHT: f64 = 2.7;
+const MONOTONICITY_WEIGHT: f64 = 1.0;
+const SMOOTHNESS_WEIGHT: f64 = 0.1;
+const MAX_TILE_WEIGHT: f64 = 1.0;
+const GAME_OVER_PENALTY: f64 = 1e6;
+
+fn rank(tile: u32) -> f64 {
+    if tile == 0 {
+        0.0
+    } else {
+        tile.trailing_zeros() as f64
+    }
+}
+
+// Penalizes lines that are not sorted either way, and big steps between
+// neighbouring tiles.
+fn score_line<I: Iterator<Item = u32>>(tiles: I) -> (f64, f64) {
+    let (mut inc, mut dec, mut smoothness) = (0.0, 0.0, 0.0);
+    let mut prev: Option<f64> = None;
+    for tile in tiles {
+        let cur = rank(tile);
+        if let Some(prev) = prev {
+            if prev > cur {
+                dec += prev - cur;
+            } else {
+                inc += cur - prev;
+            }
+            if prev != 0.0 && cur != 0.0 {
+                smoothness -= (prev - cur).abs();
+            }
+        }
+        prev = Some(cur);
+    }
+    (-f64::min(inc, dec), smoothness)
+}
+
+fn evaluate(board: &Board) -> f64 {
+    let (w, h) = (board.width(), board.height());
+    let mut monotonicity = 0.0;
+    let mut smoothness = 0.0;
+    for row in 0..h {
+        let (m, s) = score_line((0..w).map(|col| board.get(row, col)));
+        monotonicity += m;
+        smoothness += s;
+    }
+    for col in 0..w {
+        let (m, s) = score_line((0..h).map(|row| board.get(row, col)));
+        monotonicity += m;
+        smoothness += s;
+    }
+    let empty = board.holes().len() as f64;
+
+    EMPTY_WEIGHT * empty
+        + MONOTONICITY_WEIGHT * monotonicity
+        + SMOOTHNESS_WEIGHT * smoothness
+        + MAX_TILE_WEIGHT * rank(board.max_tile())
+}
+
+fn max_node(board: &Board, depth: usize) -> f64 {
+    DIRECTIONS
+        .iter()
+        .filter_map(|dir| {
+            let mut next = board.clone();
+            next.slide(*dir).map(|_| next)
+        })
+        .map(|next| chance_node(&next, depth))
+        .fold(None, |best: Option<f64>, v| {
+            Some(best.map_or(v, |b| b.max(v)))
+        })
+        .unwrap_or_else(|| evaluate(board) - GAME_OVER_PENALTY)
+}
+
+fn chance_node(board: &Board, depth: usize) -> f64 {
+    let holes = board.holes();
+    if depth == 0 || holes.is_empty() {
+        return evaluate(board);
+    }
+
+    let p2 = twenty48::two_probability(holes.len());
+    let mut total = 0.0;
+    let mut next = board.clone();
+    for i in holes.iter() {
+        next.set(*i, 2);
+        total += p2 * max_node(&next, depth - 1);
+        next.set(*i, 4);
+        total += (1.0 - p2) * max_node(&next, depth - 1);
+        next.set(*i, 0);
+    }
+    total / holes.len() as f64
+}
+
+/// Returns the most promising slide for the board, `None` if no slide moves.
+pub fn best_move(board: &Board) -> Option<Direction> {
+    // Search deeper when the board is crowded: that is when it matters most
+    // and the chance nodes are cheap.
+    let depth = match board.holes().len() {
+        0..=4 => 3,
+        5..=16 => 2,
+        _ => 1,
+    };
+
+    let mut best: Option<(Direction, f64)> = None;
+    for dir in DIRECTIONS.iter() {
+        let mut next = board.clone();
+        if next.slide(*dir).is_some() {
+            let value = chance_node(&next, depth);
+            if best.is_none_or(|(_, v)| value > v) {
+                best = Some((*dir, value));
+            }
+        }
+    }
+    best.map(|(dir, _)| dir)
+}
@@ -1,24 +1,102 @@
+extern crate game_rs;
 extern crate rand;
 extern crate termion;
 
-use std::collections::{HashMap, HashSet};
+mod ai;
+mod save;
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::io::{self, Read, Write};
+use std::mem;
+use std::process;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use rand::random;
+use game_rs::replay::{Playback, Replay, Speed};
+use game_rs::rng::{self, SplitMix64};
+use game_rs::scores::{self, NameInput, Scores};
+use game_rs::twenty48::{Board, Direction};
+use rand::Rng;
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
-use termion::{clear, color, cursor, style};
+use termion::{async_stdin, clear, color, cursor, style};
+
+const MIN_SIZE: usize = 2;
+const MAX_SIZE: usize = 16;
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 4;
+const HEADER_HEIGHT: usize = 4;
+// The narrowest a box over the board gets.
+const MENU_WIDTH: usize = 15;
+const HISTORY_SIZE: usize = 128;
+const AUTOPLAY_DELAY: u64 = 100;
+const DEFAULT_TARGET: u32 = 2048;
+const REPLAY_FRAME: Duration = Duration::from_millis(10);
+const FAST_FORWARD: u64 = 8;
+
+struct Config {
+    width: usize,
+    height: usize,
+    ranked: bool,
+    seed: u64,
+    // The tile that wins the game.
+    target: u32,
+    // The delay between moves made by the AI, if it plays the game.
+    autoplay: Option<Duration>,
+    record: Option<String>,
+    replay: Option<(Replay, Speed)>,
+    // Whether quitting saves the game and finishing it clears the saved one;
+    // a saved game this session neither resumed nor was told to replace is
+    // left alone.
+    save: bool,
+}
 
 struct Game<R, W: Write> {
-    score: u32,
-    grid: [u32; 16usize],
+    board: Board,
+    over: bool,
+    // The seed the current game started from; `--seed` replays it.
+    seed: u64,
+    rng: SplitMix64,
+    // Ranked games have undo/redo disabled, so the score is clean.
+    ranked: bool,
+    target: u32,
+    // Whether the current game reached the target tile, and how it got there
+    // while the "You win!" screen is up.
+    won: bool,
+    win: Option<Win>,
+    // When the current game started, on the clock of `now`.
+    started: u64,
+    // Boards after each move, including the tile spawned by it.
+    history: VecDeque<Board>,
+    redos: Vec<Board>,
+    autoplay: Option<Duration>,
+    hint: Option<Direction>,
+    // Whether the current game had its chance at the high-score table, the
+    // name being typed in for it, and where it ended up.
+    scored: bool,
+    naming: Option<NameInput>,
+    placed: Option<String>,
+    // When the session started; replays are stamped in milliseconds since.
+    clock: Instant,
+    recorder: Option<Replay>,
+    playback: Option<Playback>,
+    // The replay's own clock, which stops while paused and runs faster when
+    // fast-forwarding.
+    replay_clock: u64,
+    replay_frame: Instant,
     bgs: HashMap<u32, color::Bg<color::Rgb>>,
     stdin: R,
     stdout: W,
 }
 
-fn init<R: Read, W: Write>(stdin: R, mut stdout: W) {
+fn init<R: Read, W: Write>(
+    stdin: R,
+    mut stdout: W,
+    config: Config,
+    saved: Option<save::SaveGame>,
+) -> io::Result<()> {
     write!(stdout, "{}", clear::All).unwrap();
 
     let mut bgs: HashMap<u32, color::Bg<color::Rgb>> = HashMap::with_capacity(17);
@@ -44,20 +122,64 @@ fn init<R: Read, W: Write>(stdin: R, mut stdout: W) {
         color::Bg(color::Rgb(255, 0, 0)),
     ];
     bgs.insert(0u32, colors[0]);
-    for i in 1..17 {
-        bgs.insert(1u32 << i as u32, colors[i]);
+    for (i, bg) in colors.iter().enumerate().skip(1) {
+        bgs.insert(1u32 << i as u32, *bg);
     }
 
+    let recorder = config.record.as_ref().map(|_| {
+        let mut replay = Replay::new("2048", config.seed);
+        replay.add_option("size", &format!("{}x{}", config.width, config.height));
+        replay.add_option("ranked", if config.ranked { "1" } else { "0" });
+        replay.add_option("target", &config.target.to_string());
+        replay
+    });
+    let playback = config
+        .replay
+        .as_ref()
+        .map(|(replay, speed)| Playback::new(replay, *speed));
     let mut game = Game {
-        score: 0,
-        grid: [0u32; 16usize],
-        bgs: bgs,
+        board: Board::new(config.width, config.height),
+        over: false,
+        seed: config.seed,
+        rng: SplitMix64::new(config.seed),
+        ranked: config.ranked,
+        target: config.target,
+        won: false,
+        win: None,
+        started: 0,
+        history: VecDeque::with_capacity(HISTORY_SIZE),
+        redos: Vec::new(),
+        autoplay: config.autoplay,
+        hint: None,
+        scored: false,
+        naming: None,
+        placed: None,
+        clock: Instant::now(),
+        recorder,
+        playback,
+        replay_clock: 0,
+        replay_frame: Instant::now(),
+        bgs,
         stdin: stdin.keys(),
-        stdout: stdout,
+        stdout,
     };
 
-    game.reset();
+    match saved {
+        Some(saved) => game.resume(saved),
+        None => game.reset(),
+    }
     game.start();
+
+    if let (Some(replay), Some(path)) = (game.recorder.take(), config.record) {
+        replay.write(path)?;
+    }
+    if !config.save {
+        Ok(())
+    } else if game.over {
+        save::remove()
+    } else {
+        game.to_save().write()
+    }
 }
 
 impl<R, W: Write> Drop for Game<R, W> {
@@ -70,11 +192,19 @@ impl<R, W: Write> Drop for Game<R, W> {
             cursor::Goto(1, 1)
         )
         .unwrap();
+        self.stdout.flush().unwrap();
     }
 }
 
+// How a game reached the target tile.
+struct Win {
+    moves: u32,
+    // Milliseconds since the game started.
+    time: u64,
+}
+
 macro_rules! continue_if_game_over {
-    ($game_over:ident) => {
+    ($game_over:expr) => {
         if $game_over {
             continue;
         }
@@ -82,130 +212,344 @@ macro_rules! continue_if_game_over {
 }
 
 impl<R: Iterator<Item = Result<Key, io::Error>>, W: Write> Game<R, W> {
-    fn pos(&self, x: i32, y: i32) -> usize {
-        (x * 4 + y) as usize
-    }
-
-    fn get_by_pos(&self, x: i32, y: i32) -> u32 {
-        self.grid[self.pos(x, y)]
-    }
-
-    fn set_by_pos(&mut self, x: i32, y: i32, val: u32) {
-        self.grid[self.pos(x, y)] = val;
-    }
-
     fn start(&mut self) {
-        let mut game_over = false;
-
         loop {
-            let mut moved = false;
-            let b = self.stdin.next().unwrap().unwrap();
+            let b = match self.next_key() {
+                Some(b) => b,
+                None => return,
+            };
 
-            match b {
-                Key::Up | Key::Char('w') | Key::Char('k') => {
-                    continue_if_game_over!(game_over);
-                    moved = self.up();
+            if self.win.is_some() {
+                match b {
+                    Key::Char('c') | Key::Char('\n') => {
+                        self.win = None;
+                        self.redraw();
+                    }
+                    Key::Char('r') => self.restart(),
+                    Key::Esc | Key::Char('q') => return,
+                    _ => {}
                 }
-                Key::Right | Key::Char('d') | Key::Char('l') => {
-                    continue_if_game_over!(game_over);
-                    moved = self.right();
+                continue;
+            }
+
+            let dir = match b {
+                Key::Up | Key::Char('w') | Key::Char('k') => Direction::Up,
+                Key::Right | Key::Char('d') | Key::Char('l') => Direction::Right,
+                Key::Down | Key::Char('s') | Key::Char('j') => Direction::Down,
+                Key::Left | Key::Char('a') | Key::Char('h') => Direction::Left,
+                Key::Char('H') => {
+                    continue_if_game_over!(self.over);
+                    self.hint();
+                    continue;
                 }
-                Key::Down | Key::Char('s') | Key::Char('j') => {
-                    continue_if_game_over!(game_over);
-                    moved = self.down();
+                Key::Char('u') => {
+                    if self.undo() {
+                        self.over = false;
+                        self.draw();
+                    }
+                    continue;
                 }
-                Key::Left | Key::Char('a') | Key::Char('h') => {
-                    continue_if_game_over!(game_over);
-                    moved = self.left();
+                Key::Ctrl('r') | Key::Char('U') => {
+                    if self.redo() {
+                        self.draw();
+                        if self.board.is_over() {
+                            self.game_over();
+                        }
+                    }
+                    continue;
                 }
                 Key::Char('r') => {
                     self.restart();
-                    return;
+                    continue;
                 }
                 Key::Esc | Key::Char('q') => return,
                 _ => continue,
-            }
+            };
 
-            if !moved || game_over {
-                continue;
-            }
-            let full_filled = self.fill_up();
-            self.draw();
-            if full_filled && self.game_over() {
-                game_over = true;
-                self.pop_menu();
-            }
+            continue_if_game_over!(self.over);
+            self.shift(dir);
         }
     }
 
-    fn reset(&mut self) {
-        for i in 0..16usize {
-            self.grid[i] = 0u32;
+    fn shift(&mut self, dir: Direction) {
+        let prev = self.board.clone();
+        if self.board.slide(dir).is_none() {
+            return;
         }
-        self.score = 0u32;
-        let idx1 = random::<u8>() as usize % 16usize;
-        self.grid[idx1] = 2u32;
-        loop {
-            let idx2 = random::<u8>() as usize % 16usize;
-            if idx1 != idx2 {
-                self.grid[idx2] = 2u32;
-                break;
-            }
+
+        self.hint = None;
+        self.board.spawn(&mut self.rng);
+        self.remember(prev);
+        self.draw();
+        if !self.won && self.board.max_tile() >= self.target {
+            self.won = true;
+            self.win = Some(Win {
+                moves: self.board.moves(),
+                time: self.now() - self.started,
+            });
+            self.pop_win();
+        }
+        if self.board.is_over() {
+            self.game_over();
         }
+    }
+
+    // Milliseconds on the session clock, or on the replay's clock when
+    // playing one back, so a replay shows the times of the recording.
+    fn now(&self) -> u64 {
+        match self.playback {
+            Some(_) => self.replay_clock,
+            None => self.clock.elapsed().as_millis() as u64,
+        }
+    }
+
+    fn redraw(&mut self) {
         self.draw();
+        if self.win.is_some() {
+            self.pop_win();
+        } else if self.over {
+            self.pop_menu();
+        }
     }
 
-    fn restart(&mut self) {
-        self.reset();
-        self.start();
+    fn game_over(&mut self) {
+        self.over = true;
+        // Neither the AI nor a replay earns a place on the table.
+        if !self.scored && self.autoplay.is_none() && self.playback.is_none() {
+            self.scored = true;
+            let score = self.board.score() as u64;
+            if Scores::load().is_ok_and(|s| s.qualifies("2048", &self.board_name(), score)) {
+                self.naming = Some(NameInput::new());
+            }
+        }
+        self.redraw();
+    }
+
+    // The board size, apart for ranked games, which neither undo nor take
+    // hints, so `--scores` tells the clean scores from the helped ones.
+    fn board_name(&self) -> String {
+        let mode = if self.ranked { "-ranked" } else { "" };
+        format!("{}x{}{}", self.board.width(), self.board.height(), mode)
     }
 
-    fn fill_up(&mut self) -> bool {
-        let mut holes = HashSet::new();
-        for i in 0..16usize {
-            if self.grid[i] == 0u32 {
-                holes.insert(i);
+    fn enter_name(&mut self, key: Key) {
+        match key {
+            Key::Char('\n') => {
+                let name = self.naming.take().unwrap();
+                self.placed = Some(self.place(name.as_str()));
             }
+            Key::Esc => self.naming = None,
+            Key::Backspace => self.naming.as_mut().unwrap().pop(),
+            Key::Char(c) => self.naming.as_mut().unwrap().push(c),
+            _ => return,
         }
+        self.redraw();
+    }
 
-        let len = holes.len();
-        if len == 0 {
-            return true;
+    // Puts the score on the table and describes where it landed.
+    fn place(&self, name: &str) -> String {
+        let mut table = match Scores::load() {
+            Ok(table) => table,
+            Err(_) => return "  not saved".to_string(),
+        };
+        let score = self.board.score() as u64;
+        match table.insert("2048", &self.board_name(), score, name) {
+            Some(rank) if table.save().is_ok() => format!("  rank: #{}", rank),
+            Some(_) => "  not saved".to_string(),
+            None => "  not placed".to_string(),
         }
-        for idx in holes.iter() {
-            // use HashSet's random feature directly..
-            // self.grid[*idx] = 1u32 << (*idx as u32 % 2u32 + 1u32);  // whatever...
-            let mut threhold = 222u8;
-            if len <= 4 {
-                threhold = 127u8;
+    }
+
+    // The next key to play: typed by the player or made up by the AI, or the
+    // next one of a replay. Returns `None` to quit.
+    fn next_key(&mut self) -> Option<Key> {
+        loop {
+            let key = match self.stdin.next() {
+                Some(key) => key.unwrap(),
+                None if self.playback.is_some() => match self.play_back() {
+                    Some(key) => return Some(key),
+                    None => continue,
+                },
+                None => match self.autoplay {
+                    Some(delay) => match self.autoplay(delay) {
+                        Some(key) => key,
+                        None => continue,
+                    },
+                    None => return None,
+                },
+            };
+            if self.naming.is_some() && self.win.is_none() {
+                self.enter_name(key);
+                continue;
+            }
+            if self.playback.is_some() {
+                if !self.control_playback(key) {
+                    return None;
+                }
+                continue;
+            }
+            if let (Some(code), Some(recorder)) = (key_code(key), self.recorder.as_mut()) {
+                recorder.push(self.clock.elapsed().as_millis() as u64, code);
             }
-            if random::<u8>() <= threhold {
-                self.grid[*idx] = 2u32;
+            return Some(key);
+        }
+    }
+
+    fn autoplay(&mut self, delay: Duration) -> Option<Key> {
+        sleep(delay);
+        // The AI always keeps going after a win.
+        if self.win.is_some() {
+            return Some(Key::Char('c'));
+        }
+        if self.over {
+            return None;
+        }
+        ai::best_move(&self.board).map(|dir| match dir {
+            Direction::Up => Key::Up,
+            Direction::Right => Key::Right,
+            Direction::Down => Key::Down,
+            Direction::Left => Key::Left,
+        })
+    }
+
+    fn play_back(&mut self) -> Option<Key> {
+        let elapsed = self.replay_frame.elapsed().as_millis() as u64;
+        self.replay_frame = Instant::now();
+        let playback = self.playback.as_mut().unwrap();
+        if playback.advance() {
+            if playback.is_paused() {
+                // A single step jumps straight to the next event.
+                self.replay_clock = playback.next_at().unwrap_or(self.replay_clock);
+            } else if playback.is_fast() {
+                self.replay_clock += elapsed * FAST_FORWARD;
             } else {
-                self.grid[*idx] = 4u32;
+                self.replay_clock += elapsed;
+            }
+        }
+        match playback.pop_due(self.replay_clock) {
+            Some(code) => Some(Key::Char(code)),
+            None => {
+                sleep(REPLAY_FRAME);
+                None
             }
-            break;
         }
+    }
 
-        (len - 1) == 0
+    // Keys only steer the playback of a replay. Returns false to quit.
+    fn control_playback(&mut self, key: Key) -> bool {
+        {
+            let playback = self.playback.as_mut().unwrap();
+            match key {
+                Key::Char(' ') => playback.toggle_pause(),
+                Key::Char('.') => playback.step(),
+                Key::Char('f') => playback.toggle_fast(),
+                Key::Esc | Key::Char('q') => return false,
+                _ => return true,
+            }
+        }
+        self.redraw();
+        true
     }
 
-    fn game_over(&mut self) -> bool {
-        for x in 0..4 {
-            for y in 1..4 {
-                if self.get_by_pos(x, y - 1) == self.get_by_pos(x, y) {
-                    return false;
-                }
+    fn tag(&self) -> &'static str {
+        match self.playback {
+            Some(ref playback) if playback.is_finished() => "END ",
+            Some(ref playback) if playback.is_paused() => "PAUSED ",
+            Some(ref playback) if playback.is_fast() => "FAST ",
+            Some(_) => "REPLAY ",
+            None if self.ranked => "RANKED ",
+            None => "",
+        }
+    }
+
+    // Hints are not available in ranked games.
+    fn hint(&mut self) {
+        if self.ranked {
+            return;
+        }
+        self.hint = ai::best_move(&self.board);
+        self.draw();
+    }
+
+    fn to_save(&self) -> save::SaveGame {
+        save::SaveGame {
+            board: self.board.clone(),
+            ranked: self.ranked,
+            seed: self.seed,
+            rng: self.rng.state(),
+        }
+    }
+
+    fn resume(&mut self, saved: save::SaveGame) {
+        self.history.clear();
+        self.redos.clear();
+        self.over = false;
+        self.seed = saved.seed;
+        self.rng = SplitMix64::new(saved.rng);
+        self.board = saved.board;
+        // The time of a resumed game counts from the resume.
+        self.won = self.board.max_tile() >= self.target;
+        self.win = None;
+        self.started = self.now();
+        self.draw();
+        if self.board.is_over() {
+            self.game_over();
+        }
+    }
+
+    fn remember(&mut self, prev: Board) {
+        if self.ranked {
+            return;
+        }
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(prev);
+        self.redos.clear();
+    }
+
+    fn undo(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(prev) => {
+                let current = mem::replace(&mut self.board, prev);
+                self.redos.push(current);
+                true
             }
+            None => false,
         }
-        for y in 0..4 {
-            for x in 1..4 {
-                if self.get_by_pos(x - 1, y) == self.get_by_pos(x, y) {
-                    return false;
-                }
+    }
+
+    fn redo(&mut self) -> bool {
+        match self.redos.pop() {
+            Some(next) => {
+                let current = mem::replace(&mut self.board, next);
+                self.history.push_back(current);
+                true
             }
+            None => false,
         }
-        return true;
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.redos.clear();
+        self.over = false;
+        self.hint = None;
+        self.scored = false;
+        self.naming = None;
+        self.placed = None;
+        self.won = false;
+        self.win = None;
+        self.started = self.now();
+        self.rng = SplitMix64::new(self.seed);
+        self.board.reset(&mut self.rng);
+        self.draw();
+    }
+
+    // Every game gets its own seed, so the one on the menu reproduces it.
+    fn restart(&mut self) {
+        self.seed = self.rng.gen::<u32>() as u64;
+        self.reset();
     }
 
     fn bg(&self, n: u32) -> color::Bg<color::Rgb> {
@@ -219,30 +563,53 @@ impl<R: Iterator<Item = Result<Key, io::Error>>, W: Write> Game<R, W> {
         write!(self.stdout, "{}", cursor::Goto(1, 1)).unwrap();
 
         // header
+        let width = self.board.width() * CELL_WIDTH;
         let header_bg = color::Bg(color::Rgb(128, 128, 128));
-        write!(self.stdout, " {}{:31}{}\n\r", header_bg, " ", style::Reset).unwrap();
-        let score = self.score.to_string();
+        let header_line = format!(
+            " {}{:space$}{}\n\r",
+            header_bg,
+            " ",
+            style::Reset,
+            space = width - 1
+        );
+        self.stdout.write_all(header_line.as_bytes()).unwrap();
+        let score = self.board.score().to_string();
+        let tag = self.tag();
         write!(
             self.stdout,
-            " {}{} SCORE: {}{:space$}{}\n\r",
+            " {}{} SCORE: {}{:space$}{}{}\n\r",
             header_bg,
             style::Bold,
             score,
             " ",
+            tag,
             style::Reset,
-            space = 23 - score.len()
+            space = (width - 9 - score.len()).saturating_sub(tag.len())
         )
         .unwrap();
-        write!(self.stdout, " {}{:31}{}\n\r", header_bg, " ", style::Reset).unwrap();
-        write!(self.stdout, " {:32}\n\r", " ").unwrap();
+        match self.hint {
+            Some(dir) => write!(
+                self.stdout,
+                " {}{} HINT: {}{:space$}{}\n\r",
+                header_bg,
+                style::Bold,
+                arrow(dir),
+                " ",
+                style::Reset,
+                space = width - 9
+            )
+            .unwrap(),
+            None => self.stdout.write_all(header_line.as_bytes()).unwrap(),
+        }
+        write!(self.stdout, " {:width$}\n\r", " ", width = width).unwrap();
 
         // body
-        for x in 0..4i32 {
+        for x in 0..self.board.height() {
             let mut up_line: String = String::new();
             let mut mid_line: String = String::new();
             let mut down_line: String = String::new();
-            for y in 0..4i32 {
-                let val = self.get_by_pos(x, y);
+            for y in 0..self.board.width() {
+                let val = self.board.get(x, y);
                 let sval = val.to_string();
                 let bg = self.bg(val);
 
@@ -273,137 +640,394 @@ impl<R: Iterator<Item = Result<Key, io::Error>>, W: Write> Game<R, W> {
             up_line.push_str("\n\r");
             mid_line.push_str("\n\r");
             down_line.push_str("\n\r");
-            self.stdout.write(up_line.as_bytes()).unwrap();
-            self.stdout.write(mid_line.as_bytes()).unwrap();
-            self.stdout.write(down_line.as_bytes()).unwrap();
+            self.stdout.write_all(up_line.as_bytes()).unwrap();
+            self.stdout.write_all(mid_line.as_bytes()).unwrap();
+            self.stdout.write_all(down_line.as_bytes()).unwrap();
             self.stdout
-                .write(format!("{:32}\n\r", " ").as_bytes())
+                .write_all(format!("{:width$}\n\r", " ", width = width).as_bytes())
                 .unwrap();
         }
         self.stdout.flush().unwrap();
     }
 
-    fn pop_menu(&mut self) {
-        let bg = color::Bg(color::Rgb(128, 128, 128));
-        write!(
-            self.stdout,
-            "{}{}{}  GAME OVER!   {}\n\r",
-            cursor::Goto(10, 4),
-            bg,
-            style::Bold,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}  restart: r   {}\n\r",
-            cursor::Goto(10, 5),
-            bg,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}  quit: ESC|q  {}\n\r",
-            cursor::Goto(10, 6),
-            bg,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}               {}\n\r",
-            cursor::Goto(10, 7),
-            bg,
-            style::Reset
-        )
-        .unwrap();
+    fn pop_win(&mut self) {
+        let win = match self.win {
+            Some(ref win) => win,
+            None => return,
+        };
+        let lines = vec![
+            "  YOU WIN!".to_string(),
+            format!("  moves: {}", win.moves),
+            format!("  time: {}", format_time(win.time)),
+            "  continue: c".to_string(),
+            "  restart: r".to_string(),
+            "  quit: ESC|q".to_string(),
+            String::new(),
+        ];
+        self.overlay(&lines);
     }
 
-    fn merge<Fget, Fset>(
-        &mut self,
-        xs: [i32; 4],
-        ys: [i32; 4],
-        mut fget: Fget,
-        mut fset: Fset,
-    ) -> bool
-    where
-        Fget: FnMut(&Game<R, W>, i32, i32) -> u32,
-        Fset: FnMut(&mut Game<R, W>, i32, i32, u32),
-    {
-        let mut moved = false; // Fuck: https://github.com/rust-lang/rust/issues/28570
-
-        for x in xs.iter() {
-            let mut prev_non_zero_y = ys[0];
-            let first_y = ys[0];
-            for y in (&ys[1..4]).iter() {
-                let mut val = fget(self, *x, *y);
-                if val != 0u32 {
-                    if prev_non_zero_y < *y && fget(self, *x, prev_non_zero_y) == val {
-                        let score = val * 2;
-                        self.score += score;
-                        fset(self, *x, prev_non_zero_y, score);
-                        val = 0u32;
-                        fset(self, *x, *y, val);
-                        prev_non_zero_y = *y + 1;
-                        moved = true;
-                    } else {
-                        prev_non_zero_y = *y;
-                    }
-                }
-
-                let mut k = *y;
-                while k > first_y {
-                    let prev = fget(self, *x, k - 1);
-                    if prev != 0u32 || val == 0u32 {
-                        break;
-                    }
-                    moved = true;
-                    fset(self, *x, k - 1, val);
-                    fset(self, *x, k, 0u32);
-                    k -= 1;
-                    prev_non_zero_y = k;
-                }
+    fn pop_menu(&mut self) {
+        let mut lines = vec!["  GAME OVER!".to_string()];
+        if let Some(ref name) = self.naming {
+            lines.push("  HIGH SCORE!".to_string());
+            lines.push(format!(" >{}_", name.as_str()));
+            lines.push("  enter: save".to_string());
+            lines.push("  ESC: skip".to_string());
+        } else {
+            lines.push("  restart: r".to_string());
+            lines.push("  quit: ESC|q".to_string());
+            lines.push(format!("  seed: {}", self.seed));
+            if let Some(ref placed) = self.placed {
+                lines.push(placed.clone());
+            }
+            if !self.ranked {
+                lines.push("  undo: u".to_string());
             }
         }
+        lines.push(String::new());
+        self.overlay(&lines);
+    }
+
+    // Pops a box over the board, as wide as its longest line and at least
+    // `MENU_WIDTH`, the first line in bold.
+    fn overlay(&mut self, lines: &[String]) {
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .fold(MENU_WIDTH, usize::max);
+        let start = ((self.board.width() * CELL_WIDTH).saturating_sub(width) / 2 + 2) as u16;
+        let bg = color::Bg(color::Rgb(128, 128, 128));
+        for (i, line) in lines.iter().enumerate() {
+            let bold = if i == 0 {
+                style::Bold.to_string()
+            } else {
+                String::new()
+            };
+            write!(
+                self.stdout,
+                "{}{}{}{:width$}{}\n\r",
+                cursor::Goto(start, 4 + i as u16),
+                bg,
+                bold,
+                line,
+                style::Reset,
+                width = width
+            )
+            .unwrap();
+        }
+    }
+}
+
+// The key a replay records for a key press; `None` for keys that do not
+// change the game.
+fn key_code(key: Key) -> Option<char> {
+    match key {
+        Key::Up | Key::Char('w') | Key::Char('k') => Some('w'),
+        Key::Right | Key::Char('d') | Key::Char('l') => Some('d'),
+        Key::Down | Key::Char('s') | Key::Char('j') => Some('s'),
+        Key::Left | Key::Char('a') | Key::Char('h') => Some('a'),
+        Key::Ctrl('r') | Key::Char('U') => Some('U'),
+        Key::Char('c') | Key::Char('\n') => Some('c'),
+        Key::Char(c @ 'H') | Key::Char(c @ 'u') | Key::Char(c @ 'r') => Some(c),
+        _ => None,
+    }
+}
 
-        moved
+// m:ss, or h:mm:ss for the patient.
+fn format_time(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
     }
+}
 
-    fn up(&mut self) -> bool {
-        let fget = |g: &Game<R, W>, x: i32, y: i32| g.get_by_pos(y, x);
-        let fset = |g: &mut Game<R, W>, x: i32, y: i32, val: u32| g.set_by_pos(y, x, val);
-        let xs = [0, 1, 2, 3];
-        let ys = [0, 1, 2, 3];
-        return self.merge(xs, ys, fget, fset);
+fn arrow(dir: Direction) -> char {
+    match dir {
+        Direction::Up => '↑',
+        Direction::Right => '→',
+        Direction::Down => '↓',
+        Direction::Left => '←',
     }
+}
 
-    fn right(&mut self) -> bool {
-        let fget = |g: &Game<R, W>, x: i32, y: i32| g.get_by_pos(-x, -y);
-        let fset = |g: &mut Game<R, W>, x: i32, y: i32, val: u32| g.set_by_pos(-x, -y, val);
-        let xs = [-3, -2, -1, 0];
-        let ys = [-3, -2, -1, 0];
-        return self.merge(xs, ys, fget, fset);
+fn parse_size(s: &str) -> Option<(usize, usize)> {
+    let mut parts = s.splitn(2, ['x', 'X']);
+    let width = parts.next()?.trim().parse::<usize>().ok()?;
+    let height = parts.next()?.trim().parse::<usize>().ok()?;
+    let range = MIN_SIZE..=MAX_SIZE;
+    if !range.contains(&width) || !range.contains(&height) {
+        return None;
     }
+    Some((width, height))
+}
 
-    fn down(&mut self) -> bool {
-        let fget = |g: &Game<R, W>, x: i32, y: i32| g.get_by_pos(-y, -x);
-        let fset = |g: &mut Game<R, W>, x: i32, y: i32, val: u32| g.set_by_pos(-y, -x, val);
-        let xs = [-3, -2, -1, 0];
-        let ys = [-3, -2, -1, 0];
-        return self.merge(xs, ys, fget, fset);
+fn parse_target(s: &str) -> Option<u32> {
+    match s.parse::<u32>() {
+        Ok(tile) if tile >= 4 && tile.is_power_of_two() => Some(tile),
+        _ => None,
     }
+}
 
-    fn left(&mut self) -> bool {
-        let fget = |g: &Game<R, W>, x: i32, y: i32| g.get_by_pos(x, y);
-        let fset = |g: &mut Game<R, W>, x: i32, y: i32, val: u32| g.set_by_pos(x, y, val);
-        let xs = [0, 1, 2, 3];
-        let ys = [0, 1, 2, 3];
-        return self.merge(xs, ys, fget, fset);
+// Asks a yes or no question, `default` being the answer to a bare enter.
+// Returns `None` if there is nobody to ask.
+fn confirm(question: &str, default: bool) -> Option<bool> {
+    if !termion::is_tty(&io::stdin()) {
+        return None;
     }
+    eprint!("{}", question);
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return None;
+    }
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Some(true),
+        "n" | "no" => Some(false),
+        _ => Some(default),
+    }
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!(
+        "{}{}{}{}",
+        style::Bold,
+        color::Fg(color::Red),
+        msg,
+        style::Reset
+    );
+    process::exit(1);
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (mut width, mut height) = (4usize, 4usize);
+    let mut ranked = false;
+    let mut resume = false;
+    let mut autoplay = false;
+    let mut delay = AUTOPLAY_DELAY;
+    let mut seed = None;
+    let mut target = DEFAULT_TARGET;
+    let mut record = None;
+    let mut replay = None;
+    let mut speed = Speed::Normal;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--help" | "-h" => {
+                eprintln!("Usage: {} [OPTIONS]", &args[0]);
+                eprintln!("\nOPTIONS:");
+                eprintln!("  --help/-h        Show this message.");
+                eprintln!(
+                    "  --size/-s WxH    The board size, {} to {} cells per side (default: 4x4).",
+                    MIN_SIZE, MAX_SIZE
+                );
+                eprintln!(
+                    "  --no-undo        Ranked mode: disable undo/redo so the score is clean."
+                );
+                eprintln!("  --resume         Resume the game saved on the last quit.");
+                eprintln!("  --autoplay       Let the AI play the game.");
+                eprintln!(
+                    "  --delay MS       Milliseconds between AI moves (default: {}).",
+                    AUTOPLAY_DELAY
+                );
+                eprintln!(
+                    "  --target N       The tile that wins the game (default: {}).",
+                    DEFAULT_TARGET
+                );
+                eprintln!("  --seed N         Start from a fixed seed to replay a game.");
+                eprintln!("  --scores         Print the high-score table and exit.");
+                eprintln!("  --record FILE    Record every key of the session to FILE.");
+                eprintln!("  --replay FILE    Play back a recorded session.");
+                eprintln!(
+                    "  --replay-speed S Playback speed: normal, fast or step (default: normal)."
+                );
+                process::exit(1);
+            }
+            "--size" | "-s" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing board size.");
+                }
+                let size = parse_size(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid board size: {}", &args[i]));
+                });
+                width = size.0;
+                height = size.1;
+            }
+            "--no-undo" => ranked = true,
+            "--resume" => resume = true,
+            "--autoplay" => autoplay = true,
+            "--delay" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing delay.");
+                }
+                delay = args[i].parse::<u64>().unwrap_or_else(|_| {
+                    exit_with_error(&format!("Invalid delay: {}", &args[i]));
+                });
+            }
+            "--seed" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing seed.");
+                }
+                seed = Some(args[i].parse::<u64>().unwrap_or_else(|_| {
+                    exit_with_error(&format!("Invalid seed: {}", &args[i]));
+                }));
+            }
+            "--target" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing target tile.");
+                }
+                target = parse_target(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!(
+                        "Invalid target tile: {} (a power of two, at least 4)",
+                        &args[i]
+                    ));
+                });
+            }
+            "--scores" => {
+                if let Err(err) = scores::print("2048") {
+                    exit_with_error(&format!(
+                        "Can not read the high-score table: {} (remove {} to start over)",
+                        err,
+                        scores::path().unwrap_or_default().display()
+                    ));
+                }
+                process::exit(0);
+            }
+            "--record" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing record file.");
+                }
+                record = Some(args[i].clone());
+            }
+            "--replay" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing replay file.");
+                }
+                replay = Some(Replay::read(&args[i], "2048").unwrap_or_else(|err| {
+                    exit_with_error(&format!("Can not read the replay {}: {}", &args[i], err));
+                }));
+            }
+            "--replay-speed" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing replay speed.");
+                }
+                speed = Speed::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid replay speed: {}", &args[i]));
+                });
+            }
+            arg => exit_with_error(&format!("Unknown option: {}", arg)),
+        }
+        i += 1;
+    }
+
+    if let Some(ref replay) = replay {
+        if resume || autoplay || record.is_some() {
+            exit_with_error("--replay can not be combined with --resume, --autoplay or --record.");
+        }
+        let size = replay.option("size").and_then(parse_size);
+        let (w, h) = size.unwrap_or_else(|| exit_with_error("The replay has no valid board size."));
+        width = w;
+        height = h;
+        ranked = replay.option("ranked") == Some("1");
+        target = match replay.option("target") {
+            Some(tile) => parse_target(tile)
+                .unwrap_or_else(|| exit_with_error("The replay has no valid target tile.")),
+            None => DEFAULT_TARGET,
+        };
+        seed = Some(replay.seed);
+    }
+    if record.is_some() && resume {
+        exit_with_error("--record needs a new game, not a resumed one.");
+    }
+
+    // A replay must not touch the saved game.
+    let saved = if replay.is_some() {
+        Ok(None)
+    } else {
+        save::SaveGame::read(MIN_SIZE, MAX_SIZE)
+    };
+    // A save that can not be read only stops `--resume`; anything else moves
+    // it aside and starts a new game, saying so once the game is over.
+    let mut warning = None;
+    let mut save = replay.is_none();
+    let saved = match saved {
+        Ok(saved) => saved,
+        Err(err) if resume => exit_with_error(&format!(
+            "Can not resume the saved game: {} (remove {} to start over)",
+            err,
+            save::path().unwrap_or_default().display()
+        )),
+        Err(err) => {
+            warning = Some(match save::set_aside() {
+                Ok(path) => format!(
+                    "Can not resume the saved game: {}; it was moved to {}.",
+                    err,
+                    path.display()
+                ),
+                Err(move_err) => {
+                    save = false;
+                    format!(
+                        "Can not resume the saved game: {}; it was left in place ({}).",
+                        err, move_err
+                    )
+                }
+            });
+            None
+        }
+    };
+    let saved = match saved {
+        Some(saved) if resume => Some(saved),
+        // An explicit seed or a recording asks for a fresh game, which only
+        // takes the saved one's place if the player says so.
+        Some(_) if seed.is_some() || record.is_some() => {
+            save = confirm(
+                "A saved game exists; replace it with this one when you quit? [y/N] ",
+                false,
+            ) == Some(true);
+            None
+        }
+        Some(saved) => match confirm("Resume the saved game? [Y/n] ", true) {
+            Some(true) => Some(saved),
+            // Declined, the saved game gives way to the new one.
+            Some(false) => None,
+            None => {
+                save = false;
+                None
+            }
+        },
+        None if resume => exit_with_error("No saved game to resume."),
+        None => None,
+    };
+    if let Some(ref saved) = saved {
+        width = saved.board.width();
+        height = saved.board.height();
+        ranked = saved.ranked;
+    }
+    let config = Config {
+        width,
+        height,
+        ranked,
+        seed: seed.unwrap_or_else(rng::random_seed),
+        target,
+        autoplay: if autoplay {
+            Some(Duration::from_millis(delay))
+        } else {
+            None
+        },
+        record,
+        replay: replay.map(|replay| (replay, speed)),
+        save,
+    };
+
     let stdout = io::stdout();
     let stdout = stdout.lock();
     let stdout = stdout.into_raw_mode().unwrap();
@@ -414,16 +1038,31 @@ fn main() {
 
     let termsize = termion::terminal_size().ok();
     let (termwidth, termheight) = termsize.map(|(w, h)| (w - 2, h - 2)).unwrap();
-    if termwidth < 32 || termheight < 20 {
+    let (minwidth, minheight) = (width * CELL_WIDTH, height * CELL_HEIGHT + HEADER_HEIGHT);
+    if (termwidth as usize) < minwidth || (termheight as usize) < minheight {
         write!(
             stderr,
-            "{}{}terminal size must satisfy with (width >= 32, height >= 20){}",
+            "{}{}terminal size must satisfy with (width >= {}, height >= {}){}",
             style::Bold,
             color::Fg(color::Red),
+            minwidth,
+            minheight,
             style::Reset
         )
         .unwrap();
     } else {
-        init(stdin, stdout);
+        // The AI and replays must not block on the keyboard, but still listen
+        // for keys.
+        let result = if config.autoplay.is_some() || config.replay.is_some() {
+            init(async_stdin(), stdout, config, saved)
+        } else {
+            init(stdin, stdout, config, saved)
+        };
+        if let Err(err) = result {
+            exit_with_error(&format!("Can not save the game or recording: {}", err));
+        }
+    }
+    if let Some(warning) = warning {
+        eprintln!("{}", warning);
     }
 }
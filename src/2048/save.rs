@@ -0,0 +1,312 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use game_rs::data::data_dir;
+use game_rs::twenty48::Board;
+
+const HEADER: &str = "game-rs 2048 save";
+const VERSION: u32 = 2;
+
+/// An in-progress game as written to disk on quit.
+pub struct SaveGame {
+    pub board: Board,
+    pub ranked: bool,
+    pub seed: u64,
+    pub rng: u64,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Version(String),
+    Invalid { line: usize, reason: String },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Version(header) => write!(
+                f,
+                "unsupported save format {:?}, expected \"{} v{}\"",
+                header, HEADER, VERSION
+            ),
+            SaveError::Invalid { line, reason } => {
+                write!(f, "corrupt save at line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("2048.save"))
+}
+
+pub fn remove() -> io::Result<()> {
+    match path() {
+        Some(path) => match fs::remove_file(path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+        None => Ok(()),
+    }
+}
+
+/// Moves a save that can not be read out of the way, next to where it was.
+pub fn set_aside() -> io::Result<PathBuf> {
+    let path = path().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "can not locate the data directory")
+    })?;
+    let aside = path.with_extension("save.bad");
+    fs::rename(&path, &aside)?;
+    Ok(aside)
+}
+
+impl SaveGame {
+    pub fn write(&self) -> io::Result<()> {
+        let path = path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "can not locate the data directory")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = String::new();
+        content.push_str(&format!("{} v{}\n", HEADER, VERSION));
+        let board = &self.board;
+        content.push_str(&format!("size {}x{}\n", board.width(), board.height()));
+        content.push_str(&format!("ranked {}\n", self.ranked as u8));
+        content.push_str(&format!("score {}\n", board.score()));
+        content.push_str(&format!("moves {}\n", board.moves()));
+        content.push_str(&format!("seed {}\n", self.seed));
+        content.push_str(&format!("rng {:016x}\n", self.rng));
+        for row in board.cells().chunks(board.width()) {
+            let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            content.push_str(&format!("grid {}\n", row.join(" ")));
+        }
+
+        // Write aside and rename, so a crash never leaves half a save behind.
+        let tmp = path.with_extension("save.tmp");
+        fs::File::create(&tmp)?.write_all(content.as_bytes())?;
+        fs::rename(tmp, path)
+    }
+
+    /// Returns `Ok(None)` if there is no saved game.
+    pub fn read(min_size: usize, max_size: usize) -> Result<Option<SaveGame>, SaveError> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(SaveError::Io(err)),
+        };
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::parse(&lines, min_size, max_size).map(Some)
+    }
+
+    fn parse(lines: &[String], min_size: usize, max_size: usize) -> Result<SaveGame, SaveError> {
+        let header = lines.first().map(|s| s.as_str()).unwrap_or("");
+        if header != format!("{} v{}", HEADER, VERSION) {
+            return Err(SaveError::Version(header.to_string()));
+        }
+
+        let mut fields = lines.iter().enumerate().skip(1).map(|(i, line)| {
+            let mut parts = line.splitn(2, ' ');
+            (
+                i + 1,
+                parts.next().unwrap_or(""),
+                parts.next().unwrap_or(""),
+            )
+        });
+        let mut field = |name: &str| -> Result<(usize, String), SaveError> {
+            match fields.next() {
+                Some((line, key, value)) if key == name => Ok((line, value.to_string())),
+                Some((line, key, _)) => Err(invalid(
+                    line,
+                    format!("expected field {:?}, found {:?}", name, key),
+                )),
+                None => Err(invalid(lines.len(), format!("missing field {:?}", name))),
+            }
+        };
+
+        let (line, size) = field("size")?;
+        let (width, height) = {
+            let mut parts = size.splitn(2, 'x');
+            let width = parts.next().and_then(|s| s.parse::<usize>().ok());
+            let height = parts.next().and_then(|s| s.parse::<usize>().ok());
+            match (width, height) {
+                (Some(w), Some(h))
+                    if in_range(w, min_size, max_size) && in_range(h, min_size, max_size) =>
+                {
+                    (w, h)
+                }
+                _ => return Err(invalid(line, format!("bad board size {:?}", size))),
+            }
+        };
+        let (line, ranked) = field("ranked")?;
+        let ranked = match ranked.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Err(invalid(line, format!("bad ranked flag {:?}", ranked))),
+        };
+        let (line, score) = field("score")?;
+        let score = score
+            .parse::<u32>()
+            .map_err(|_| invalid(line, format!("bad score {:?}", score)))?;
+        let (line, moves) = field("moves")?;
+        let moves = moves
+            .parse::<u32>()
+            .map_err(|_| invalid(line, format!("bad move count {:?}", moves)))?;
+        let (line, seed) = field("seed")?;
+        let seed = seed
+            .parse::<u64>()
+            .map_err(|_| invalid(line, format!("bad seed {:?}", seed)))?;
+        let (line, rng) = field("rng")?;
+        let rng = u64::from_str_radix(&rng, 16)
+            .map_err(|_| invalid(line, format!("bad rng state {:?}", rng)))?;
+
+        let mut grid = Vec::with_capacity(width * height);
+        for _ in 0..height {
+            let (line, row) = field("grid")?;
+            let row = row
+                .split_whitespace()
+                .map(|v| match v.parse::<u32>() {
+                    Ok(v) if v == 0 || (v >= 2 && v.is_power_of_two()) => Ok(v),
+                    _ => Err(invalid(line, format!("bad tile {:?}", v))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if row.len() != width {
+                return Err(invalid(
+                    line,
+                    format!("expected {} tiles, found {}", width, row.len()),
+                ));
+            }
+            grid.extend(row);
+        }
+        if let Some((line, _, _)) = fields.next() {
+            return Err(invalid(line, "unexpected trailing data".to_string()));
+        }
+        if grid.iter().all(|v| *v == 0) {
+            return Err(invalid(lines.len(), "the board is empty".to_string()));
+        }
+
+        // The tiles are checked above, so this only fails on a size mismatch.
+        let board = Board::from_cells(width, height, grid, score, moves)
+            .ok_or_else(|| invalid(lines.len(), "the board does not fit its size".to_string()))?;
+        Ok(SaveGame {
+            board,
+            ranked,
+            seed,
+            rng,
+        })
+    }
+}
+
+fn in_range(n: usize, min: usize, max: usize) -> bool {
+    n >= min && n <= max
+}
+
+fn invalid(line: usize, reason: String) -> SaveError {
+    SaveError::Invalid { line, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    fn save_text() -> String {
+        format!(
+            "{} v{}\nsize 3x2\nranked 1\nscore 48\nmoves 17\nseed 42\nrng 00000000deadbeef\ngrid 2 0 4\ngrid 0 16 8\n",
+            HEADER, VERSION
+        )
+    }
+
+    #[test]
+    fn write_then_read() {
+        let dir = env::temp_dir().join(format!("game-rs-save-test-{}", process::id()));
+        env::set_var("XDG_DATA_HOME", &dir);
+        let board = Board::from_cells(3, 2, vec![2, 0, 4, 0, 16, 8], 48, 17).unwrap();
+        let saved = SaveGame {
+            board: board.clone(),
+            ranked: true,
+            seed: 42,
+            rng: 0xdead_beef,
+        };
+        saved.write().unwrap();
+        let read = SaveGame::read(2, 16).unwrap().unwrap();
+        assert_eq!(read.board, board);
+        assert!(read.ranked);
+        assert_eq!((read.seed, read.rng), (42, 0xdead_beef));
+
+        remove().unwrap();
+        assert!(SaveGame::read(2, 16).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_save() {
+        let saved = SaveGame::parse(&lines(&save_text()), 2, 16).unwrap();
+        assert_eq!(saved.board.cells(), &[2, 0, 4, 0, 16, 8]);
+        assert_eq!((saved.board.score(), saved.board.moves()), (48, 17));
+        assert_eq!(saved.rng, 0xdead_beef);
+    }
+
+    #[test]
+    fn wrong_header() {
+        let text = save_text().replace(" v2", " v1");
+        match SaveGame::parse(&lines(&text), 2, 16) {
+            Err(SaveError::Version(header)) => assert_eq!(header, "game-rs 2048 save v1"),
+            _ => panic!("expected a version error"),
+        }
+    }
+
+    fn invalid_line(text: &str) -> usize {
+        match SaveGame::parse(&lines(text), 2, 16) {
+            Err(SaveError::Invalid { line, .. }) => line,
+            _ => panic!("expected a corrupt save"),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let text = save_text();
+        let cut: Vec<&str> = text.lines().take(8).collect();
+        assert_eq!(invalid_line(&cut.join("\n")), 8);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(invalid_line(&save_text().replace("3x2", "3x20")), 2);
+        assert_eq!(
+            invalid_line(&save_text().replace("ranked 1", "ranked 2")),
+            3
+        );
+        assert_eq!(invalid_line(&save_text().replace("16 8", "15 8")), 9);
+        assert_eq!(
+            invalid_line(&save_text().replace("grid 2 0 4", "grid 2 0")),
+            8
+        );
+        assert_eq!(invalid_line(&format!("{}grid 2 2 2\n", save_text())), 10);
+    }
+}
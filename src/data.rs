@@ -0,0 +1,13 @@
+//! Where the games keep their files.
+
+use std::env;
+use std::path::PathBuf;
+
+/// `$XDG_DATA_HOME/game-rs`, falling back to `~/.local/share/game-rs`.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(base.join("game-rs"))
+}
@@ -0,0 +1,10 @@
+//! Game rules without any terminal I/O, so they can be driven from tests,
+//! bots and other frontends as well as the bundled terminal games.
+
+extern crate rand;
+
+pub mod data;
+pub mod replay;
+pub mod rng;
+pub mod scores;
+pub mod twenty48;
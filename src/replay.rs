@@ -0,0 +1,280 @@
+//! Replay files: the seed and options a game started with, plus every key
+//! that drove it, stamped with the game's own clock (milliseconds for 2048,
+//! ticks for snake).
+//!
+//! ```text
+//! game-rs replay v1
+//! game snake
+//! seed 1234
+//! option map ..*..
+//! event 12 w
+//! ```
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+const HEADER: &str = "game-rs replay";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub at: u64,
+    pub key: char,
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub game: String,
+    pub seed: u64,
+    options: Vec<(String, String)>,
+    events: Vec<Event>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Version(String),
+    Invalid { line: usize, reason: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::Version(header) => write!(
+                f,
+                "unsupported replay format {:?}, expected \"{} v{}\"",
+                header, HEADER, VERSION
+            ),
+            ReplayError::Invalid { line, reason } => {
+                write!(f, "corrupt replay at line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl Replay {
+    pub fn new(game: &str, seed: u64) -> Self {
+        Replay {
+            game: game.to_string(),
+            seed,
+            options: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Options may repeat, e.g. one `map` option per row.
+    pub fn add_option(&mut self, name: &str, value: &str) {
+        self.options.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn option<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        self.options(name).next()
+    }
+
+    pub fn options<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.options
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn push(&mut self, at: u64, key: char) {
+        self.events.push(Event { at, key });
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut content = String::new();
+        content.push_str(&format!("{} v{}\n", HEADER, VERSION));
+        content.push_str(&format!("game {}\n", self.game));
+        content.push_str(&format!("seed {}\n", self.seed));
+        for (name, value) in self.options.iter() {
+            content.push_str(&format!("option {} {}\n", name, value));
+        }
+        for event in self.events.iter() {
+            content.push_str(&format!("event {} {}\n", event.at, event.key));
+        }
+        fs::File::create(path)?.write_all(content.as_bytes())
+    }
+
+    /// Reads a replay, rejecting it unless it was recorded by `game`.
+    pub fn read<P: AsRef<Path>>(path: P, game: &str) -> Result<Replay, ReplayError> {
+        let file = fs::File::open(path)?;
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let header = lines.first().map(|s| s.as_str()).unwrap_or("");
+        if header != format!("{} v{}", HEADER, VERSION) {
+            return Err(ReplayError::Version(header.to_string()));
+        }
+
+        let mut replay = Replay::new("", 0);
+        let (mut has_game, mut has_seed) = (false, false);
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            let lineno = i + 1;
+            let mut parts = line.splitn(2, ' ');
+            let kind = parts.next().unwrap_or("");
+            let rest = parts.next().unwrap_or("");
+            match kind {
+                "game" if rest == game => {
+                    replay.game = rest.to_string();
+                    has_game = true;
+                }
+                "game" => {
+                    return Err(invalid(
+                        lineno,
+                        format!("recorded by {:?}, not {:?}", rest, game),
+                    ))
+                }
+                "seed" => {
+                    replay.seed = rest
+                        .parse()
+                        .map_err(|_| invalid(lineno, format!("bad seed {:?}", rest)))?;
+                    has_seed = true;
+                }
+                "option" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let name = parts.next().unwrap_or("");
+                    if name.is_empty() {
+                        return Err(invalid(lineno, "option without a name".to_string()));
+                    }
+                    replay.add_option(name, parts.next().unwrap_or(""));
+                }
+                "event" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let at = parts.next().and_then(|s| s.parse::<u64>().ok());
+                    let mut key = parts.next().unwrap_or("").chars();
+                    match (at, key.next(), key.next()) {
+                        (Some(at), Some(key), None)
+                            if replay.events.last().is_none_or(|e| e.at <= at) =>
+                        {
+                            replay.push(at, key)
+                        }
+                        _ => return Err(invalid(lineno, format!("bad event {:?}", rest))),
+                    }
+                }
+                "" => {}
+                _ => return Err(invalid(lineno, format!("unknown entry {:?}", kind))),
+            }
+        }
+        if !has_game || !has_seed {
+            return Err(invalid(lines.len(), "missing game or seed".to_string()));
+        }
+        Ok(replay)
+    }
+}
+
+fn invalid(line: usize, reason: String) -> ReplayError {
+    ReplayError::Invalid { line, reason }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Normal,
+    Fast,
+    Step,
+}
+
+impl Speed {
+    pub fn parse(s: &str) -> Option<Speed> {
+        match s {
+            "normal" => Some(Speed::Normal),
+            "fast" => Some(Speed::Fast),
+            "step" => Some(Speed::Step),
+            _ => None,
+        }
+    }
+}
+
+/// Feeds the events of a replay back to a game and tracks the viewer's
+/// pause, step and fast-forward requests.
+pub struct Playback {
+    events: Vec<Event>,
+    next: usize,
+    fast: bool,
+    paused: bool,
+    steps: usize,
+}
+
+impl Playback {
+    pub fn new(replay: &Replay, speed: Speed) -> Self {
+        Playback {
+            events: replay.events.clone(),
+            next: 0,
+            fast: speed == Speed::Fast,
+            paused: speed == Speed::Step,
+            steps: 0,
+        }
+    }
+
+    pub fn is_fast(&self) -> bool {
+        self.fast
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.steps = 0;
+    }
+
+    pub fn toggle_fast(&mut self) {
+        self.fast = !self.fast;
+    }
+
+    /// Pauses the playback and lets it advance by a single step.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.steps += 1;
+    }
+
+    /// Whether the game clock may advance now; consumes a pending step.
+    pub fn advance(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        if self.steps > 0 {
+            self.steps -= 1;
+            return true;
+        }
+        false
+    }
+
+    /// The clock of the next event to play.
+    pub fn next_at(&self) -> Option<u64> {
+        self.events.get(self.next).map(|e| e.at)
+    }
+
+    /// Pops the next event if it is due at `now`.
+    pub fn pop_due(&mut self, now: u64) -> Option<char> {
+        match self.events.get(self.next) {
+            Some(event) if event.at <= now => {
+                self.next += 1;
+                Some(event.key)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! The local high-score table: the best scores of every game, kept apart per
+//! board so a 4x4 game of 2048 never competes with a 6x6 one.
+//!
+//! ```text
+//! game-rs scores v1
+//! entry 2048 4x4 20480 1792224000 alice
+//! ```
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data::data_dir;
+
+const HEADER: &str = "game-rs scores";
+const VERSION: u32 = 1;
+
+/// How many scores each table keeps.
+pub const TOP: usize = 10;
+/// The longest name the table takes.
+pub const NAME_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub game: String,
+    /// The board size or map the score was made on.
+    pub board: String,
+    pub score: u64,
+    /// Seconds since the Unix epoch.
+    pub when: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Scores {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+pub enum ScoresError {
+    Io(io::Error),
+    Version(String),
+    Invalid { line: usize, reason: String },
+}
+
+impl fmt::Display for ScoresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScoresError::Io(err) => write!(f, "{}", err),
+            ScoresError::Version(header) => write!(
+                f,
+                "unsupported score table format {:?}, expected \"{} v{}\"",
+                header, HEADER, VERSION
+            ),
+            ScoresError::Invalid { line, reason } => {
+                write!(f, "corrupt score table at line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ScoresError {}
+
+impl From<io::Error> for ScoresError {
+    fn from(err: io::Error) -> Self {
+        ScoresError::Io(err)
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("scores"))
+}
+
+impl Scores {
+    /// Reads the table, which is empty if it was never written.
+    pub fn load() -> Result<Scores, ScoresError> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(Scores::default()),
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Scores::default()),
+            Err(err) => return Err(ScoresError::Io(err)),
+        };
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let header = lines.first().map(|s| s.as_str()).unwrap_or("");
+        if header != format!("{} v{}", HEADER, VERSION) {
+            return Err(ScoresError::Version(header.to_string()));
+        }
+        let mut scores = Scores::default();
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.splitn(6, ' ').collect();
+            let entry = match parts.as_slice() {
+                ["entry", game, board, score, when, name] => {
+                    match (score.parse::<u64>(), when.parse::<u64>()) {
+                        (Ok(score), Ok(when)) => Entry {
+                            game: game.to_string(),
+                            board: board.to_string(),
+                            score,
+                            when,
+                            name: name.to_string(),
+                        },
+                        _ => return Err(invalid(i + 1, format!("bad entry {:?}", line))),
+                    }
+                }
+                _ => return Err(invalid(i + 1, format!("bad entry {:?}", line))),
+            };
+            scores.entries.push(entry);
+        }
+        Ok(scores)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "can not locate the data directory")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = format!("{} v{}\n", HEADER, VERSION);
+        for entry in self.entries.iter() {
+            content.push_str(&format!(
+                "entry {} {} {} {} {}\n",
+                entry.game, entry.board, entry.score, entry.when, entry.name
+            ));
+        }
+        let tmp = path.with_extension("tmp");
+        fs::File::create(&tmp)?.write_all(content.as_bytes())?;
+        fs::rename(tmp, path)
+    }
+
+    /// The table of one game and board, best first.
+    pub fn top(&self, game: &str, board: &str) -> Vec<&Entry> {
+        let mut top: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|e| e.game == game && e.board == board)
+            .collect();
+        // Ties go to whoever got there first.
+        top.sort_by(|a, b| b.score.cmp(&a.score).then(a.when.cmp(&b.when)));
+        top.truncate(TOP);
+        top
+    }
+
+    /// Every board `game` has a table for.
+    pub fn boards(&self, game: &str) -> Vec<&str> {
+        let mut boards: Vec<&str> = self
+            .entries
+            .iter()
+            .filter(|e| e.game == game)
+            .map(|e| e.board.as_str())
+            .collect();
+        boards.sort();
+        boards.dedup();
+        boards
+    }
+
+    /// Whether `score` would make it onto the table.
+    pub fn qualifies(&self, game: &str, board: &str, score: u64) -> bool {
+        let top = self.top(game, board);
+        score > 0 && (top.len() < TOP || top.iter().any(|e| score > e.score))
+    }
+
+    /// Adds a score made just now and returns its place on the table, 1 for
+    /// the best, or `None` if it did not make it.
+    pub fn insert(&mut self, game: &str, board: &str, score: u64, name: &str) -> Option<usize> {
+        let when = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let entry = Entry {
+            game: game.to_string(),
+            board: board.to_string(),
+            score,
+            when,
+            name: clean_name(name),
+        };
+        self.entries.push(entry.clone());
+
+        // Drop whatever fell off the bottom of the table.
+        let top: Vec<Entry> = self.top(game, board).into_iter().cloned().collect();
+        self.entries
+            .retain(|e| e.game != game || e.board != board || top.contains(e));
+        top.iter().position(|e| *e == entry).map(|i| i + 1)
+    }
+}
+
+fn invalid(line: usize, reason: String) -> ScoresError {
+    ScoresError::Invalid { line, reason }
+}
+
+/// A name fit for the table: printable, at most `NAME_LEN` characters, and
+/// "anonymous" if nothing is left.
+pub fn clean_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(NAME_LEN)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        "anonymous".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The name to offer before the player types their own.
+pub fn default_name() -> String {
+    env::var("USER")
+        .map(|name| clean_name(&name))
+        .unwrap_or_default()
+}
+
+/// A name being typed in on the game-over screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameInput {
+    name: String,
+}
+
+impl NameInput {
+    pub fn new() -> Self {
+        NameInput {
+            name: default_name(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    pub fn push(&mut self, c: char) {
+        if !c.is_control() && self.name.chars().count() < NAME_LEN {
+            self.name.push(c);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.name.pop();
+    }
+}
+
+impl Default for NameInput {
+    fn default() -> Self {
+        NameInput::new()
+    }
+}
+
+/// Prints every table of `game`, or a note if there is none yet.
+pub fn print(game: &str) -> Result<(), ScoresError> {
+    let scores = Scores::load()?;
+    let boards = scores.boards(game);
+    if boards.is_empty() {
+        println!("No {} scores yet.", game);
+    }
+    for (i, board) in boards.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", board);
+        for (rank, entry) in scores.top(game, board).iter().enumerate() {
+            println!(
+                "{:>4}. {:<width$} {:>10}  {}",
+                rank + 1,
+                entry.name,
+                entry.score,
+                date(entry.when),
+                width = NAME_LEN
+            );
+        }
+    }
+    Ok(())
+}
+
+// YYYY-MM-DD of a Unix timestamp, in UTC.
+fn date(secs: u64) -> String {
+    // From Howard Hinnant's civil_from_days.
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,397 @@
+//! Steers the snake for `--autoplay` and `--benchmark`. Where the open
+//! cells of the map make a rectangle with an even number of cells, the snake
+//! follows a Hamiltonian cycle through all of them, which never runs into
+//! itself, and cuts across it towards food while it is short. Anywhere else
+//! it takes the A* way to the nearest food when the tail can still be reached
+//! from where that way ends, and chases its tail when not.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use super::map::Map;
+use super::{object, passable, room, Key, Snake, DIRECTIONS};
+
+// Shortcuts stop once the snake takes up this share of the cycle.
+const SHORTCUT_SHARE: usize = 3;
+// Empty cells kept ahead of the head on a shortcut, over the ones it leaves
+// behind and the snake's length.
+const SHORTCUT_MARGIN: usize = 3;
+// Marks a cell off the cycle.
+const OFF: usize = usize::MAX;
+
+pub struct Autopilot {
+    cycle: Option<Cycle>,
+}
+
+struct Cycle {
+    // The place of every cell along the cycle, `OFF` for the rest, and the
+    // cell after each one.
+    order: Vec<usize>,
+    next: Vec<usize>,
+    len: usize,
+}
+
+impl Autopilot {
+    pub fn new(terrain: &Map) -> Autopilot {
+        Autopilot {
+            cycle: Cycle::build(terrain),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.cycle {
+            Some(_) => "hamiltonian cycle",
+            None => "a*",
+        }
+    }
+
+    /// The way `snake` goes next on `cells`.
+    pub fn steer(&self, terrain: &Map, cells: &[object::Object], snake: &Snake) -> Key {
+        let head = snake.body[0];
+        let step = match self.cycle {
+            Some(ref cycle) => cycle.step(terrain, cells, snake),
+            None => None,
+        };
+        step.or_else(|| seek(terrain, cells, snake))
+            .and_then(|next| direction(terrain, head, next))
+            .unwrap_or(snake.heading)
+    }
+}
+
+impl Cycle {
+    // Builds the cycle if the empty cells of the map fill a rectangle with
+    // an even side, the cells on it only.
+    fn build(terrain: &Map) -> Option<Cycle> {
+        let cols = terrain.cols;
+        let open: Vec<usize> = (0..terrain.cells.len())
+            .filter(|pos| terrain.cells[*pos] == object::SPACE)
+            .collect();
+        let top = open.iter().map(|pos| pos / cols).min()?;
+        let bottom = open.iter().map(|pos| pos / cols).max()?;
+        let left = open.iter().map(|pos| pos % cols).min()?;
+        let right = open.iter().map(|pos| pos % cols).max()?;
+        let (rows, width) = (bottom - top + 1, right - left + 1);
+        if open.len() != rows * width || rows < 2 || width < 2 || rows * width % 2 != 0 {
+            return None;
+        }
+
+        // With an even number of rows: along the top row, back and forth
+        // over the rest of the rows leaving out the first column, and up
+        // that column. An even number of columns goes the same way turned
+        // on its side.
+        let at = |a: usize, b: usize| {
+            if rows % 2 == 0 {
+                (top + a) * cols + left + b
+            } else {
+                (top + b) * cols + left + a
+            }
+        };
+        let (long, short) = if rows % 2 == 0 {
+            (rows, width)
+        } else {
+            (width, rows)
+        };
+        let mut path = Vec::with_capacity(open.len());
+        for b in 0..short {
+            path.push(at(0, b));
+        }
+        for a in 1..long {
+            if a % 2 == 1 {
+                for b in (1..short).rev() {
+                    path.push(at(a, b));
+                }
+            } else {
+                for b in 1..short {
+                    path.push(at(a, b));
+                }
+            }
+        }
+        for a in (1..long).rev() {
+            path.push(at(a, 0));
+        }
+
+        let mut cycle = Cycle {
+            order: vec![OFF; terrain.cells.len()],
+            next: vec![OFF; terrain.cells.len()],
+            len: path.len(),
+        };
+        for (i, pos) in path.iter().enumerate() {
+            cycle.order[*pos] = i;
+            cycle.next[*pos] = path[(i + 1) % path.len()];
+        }
+        Some(cycle)
+    }
+
+    // How far `to` is ahead of `from` along the cycle.
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (self.order[to] + self.len - self.order[from]) % self.len
+    }
+
+    // The next cell along the cycle, or the one furthest along it next to
+    // the head that skips no food and leaves room before the tail. The cells
+    // a shortcut skips stay empty behind the head until the tail has gone
+    // past them, which takes as many moves as the snake is long or more;
+    // the cells ahead have to hold out that long however much it eats, or
+    // it grows into its own tail. `None` if the snake is off the cycle or
+    // blocked on it.
+    fn step(&self, terrain: &Map, cells: &[object::Object], snake: &Snake) -> Option<usize> {
+        let head = snake.body[0];
+        let tail = *snake.body.back().unwrap();
+        if self.order[head] == OFF {
+            return None;
+        }
+        let mut best = self.next[head];
+        if snake.body.len() * SHORTCUT_SHARE < self.len {
+            let food = (0..cells.len())
+                .filter(|pos| object::is_food(cells[*pos]) && self.order[*pos] != OFF)
+                .map(|pos| self.distance(head, pos))
+                .min();
+            let len = snake.body.len() + 1;
+            let free = match self.distance(head, tail) {
+                0 => self.len,
+                d => d,
+            };
+            let mut best_distance = 1;
+            for dir in DIRECTIONS.iter() {
+                let next = match passable(terrain, cells, head, *dir) {
+                    Some(next) if self.order[next] != OFF => next,
+                    _ => continue,
+                };
+                let d = self.distance(head, next);
+                // From the tail to the head after the move, and the empty
+                // cells in there and ahead.
+                let span = self.distance(tail, next) + 1;
+                let (behind, ahead) = (span.saturating_sub(len), self.len - span);
+                if d > best_distance
+                    && d < free
+                    && food.is_some_and(|food| d <= food)
+                    && ahead > behind + len + SHORTCUT_MARGIN
+                {
+                    best = next;
+                    best_distance = d;
+                }
+            }
+        }
+        passable(terrain, cells, head, direction(terrain, head, best)?)
+    }
+}
+
+// The next cell on the way to food if the tail can still be reached once the
+// snake gets there, else on the way to the tail, else the one with the most
+// room.
+fn seek(terrain: &Map, cells: &[object::Object], snake: &Snake) -> Option<usize> {
+    let head = snake.body[0];
+    if let Some(path) = astar(terrain, cells, head) {
+        // Plays the way out on a copy of the board.
+        let mut cells = cells.to_vec();
+        let mut body = snake.body.clone();
+        for pos in path.iter() {
+            let grow = object::is_food(cells[*pos]);
+            body.push_front(*pos);
+            cells[*pos] = object::SNAKE_BODY;
+            if !grow {
+                let tail = body.pop_back().unwrap();
+                cells[tail] = terrain.cells[tail];
+            }
+        }
+        if reaches_tail(terrain, &cells, &body) {
+            return path.first().cloned();
+        }
+    }
+    if snake.body.len() > 1 {
+        if let Some(next) = toward_tail(terrain, cells, snake) {
+            return Some(next);
+        }
+    }
+    DIRECTIONS
+        .iter()
+        .filter_map(|dir| passable(terrain, cells, head, *dir))
+        .max_by_key(|next| room(terrain, cells, *next, cells.len()))
+}
+
+// The A* way from `start` to the nearest food, without `start`.
+fn astar(terrain: &Map, cells: &[object::Object], start: usize) -> Option<Vec<usize>> {
+    let foods: Vec<usize> = (0..cells.len())
+        .filter(|pos| object::is_food(cells[*pos]))
+        .collect();
+    if foods.is_empty() {
+        return None;
+    }
+    let estimate = |pos: usize| {
+        foods
+            .iter()
+            .map(|food| terrain.distance(pos, *food))
+            .min()
+            .unwrap()
+    };
+    let mut cost = vec![usize::MAX; cells.len()];
+    let mut from = vec![OFF; cells.len()];
+    let mut open = BinaryHeap::new();
+    cost[start] = 0;
+    open.push(Reverse((estimate(start), start)));
+    while let Some(Reverse((_, pos))) = open.pop() {
+        if pos != start && object::is_food(cells[pos]) {
+            let mut path = vec![pos];
+            let mut at = pos;
+            while from[at] != start {
+                at = from[at];
+                path.push(at);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for dir in DIRECTIONS.iter() {
+            if let Some(next) = passable(terrain, cells, pos, *dir) {
+                if cost[pos] + 1 < cost[next] {
+                    cost[next] = cost[pos] + 1;
+                    from[next] = pos;
+                    open.push(Reverse((cost[next] + estimate(next), next)));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Whether the head can get to the tail's cell in two steps or more, by when
+// the tail has moved off it.
+fn reaches_tail(terrain: &Map, cells: &[object::Object], body: &VecDeque<usize>) -> bool {
+    let (head, tail) = (body[0], *body.back().unwrap());
+    if head == tail {
+        return true;
+    }
+    let mut seen = vec![false; cells.len()];
+    let mut queue = VecDeque::new();
+    seen[head] = true;
+    queue.push_back(head);
+    while let Some(pos) = queue.pop_front() {
+        for dir in DIRECTIONS.iter() {
+            if pos != head && terrain.target(pos, *dir) == Some(tail) {
+                return true;
+            }
+            if let Some(next) = passable(terrain, cells, pos, *dir) {
+                if !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    false
+}
+
+// The first cell of the shortest way to the tail's cell that is two steps
+// long or more.
+fn toward_tail(terrain: &Map, cells: &[object::Object], snake: &Snake) -> Option<usize> {
+    let (head, tail) = (snake.body[0], *snake.body.back().unwrap());
+    let mut first = vec![OFF; cells.len()];
+    let mut queue = VecDeque::new();
+    for dir in DIRECTIONS.iter() {
+        if let Some(next) = passable(terrain, cells, head, *dir) {
+            first[next] = next;
+            queue.push_back(next);
+        }
+    }
+    while let Some(pos) = queue.pop_front() {
+        for dir in DIRECTIONS.iter() {
+            if terrain.target(pos, *dir) == Some(tail) {
+                return Some(first[pos]);
+            }
+            if let Some(next) = passable(terrain, cells, pos, *dir) {
+                if first[next] == OFF && next != head {
+                    first[next] = first[pos];
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    None
+}
+
+// Which way leads from `from` to the cell next to it.
+fn direction(terrain: &Map, from: usize, to: usize) -> Option<Key> {
+    DIRECTIONS
+        .iter()
+        .cloned()
+        .find(|dir| terrain.target(from, *dir) == Some(to))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::super::map;
+    use super::super::{Curve, Ending, Game};
+    use super::*;
+
+    fn open_map(cols: usize, rows: usize) -> Map {
+        let row = ".".repeat(cols);
+        let text = vec![row; rows].join("\n");
+        map::parse(&text).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // Follows the cycle from one of its cells all the way round.
+    fn check_cycle(terrain: &Map) {
+        let cycle = Cycle::build(terrain).expect("no cycle");
+        let cols = terrain.cols;
+        let open = terrain
+            .cells
+            .iter()
+            .filter(|obj| **obj == object::SPACE)
+            .count();
+        assert_eq!(cycle.len, open);
+        let start = cycle.order.iter().position(|i| *i == 0).unwrap();
+        let mut seen = vec![false; terrain.cells.len()];
+        let mut pos = start;
+        for _ in 0..cycle.len {
+            assert_eq!(terrain.cells[pos], object::SPACE);
+            assert!(!seen[pos], "cell {} twice", pos);
+            seen[pos] = true;
+            let next = cycle.next[pos];
+            let (dr, dc) = (
+                (pos / cols).abs_diff(next / cols),
+                (pos % cols).abs_diff(next % cols),
+            );
+            assert_eq!(dr + dc, 1, "{} to {} is no step", pos, next);
+            pos = next;
+        }
+        assert_eq!(pos, start);
+    }
+
+    #[test]
+    fn rectangles() {
+        for &(cols, rows) in [(4, 4), (6, 3), (3, 6), (2, 2), (8, 5)].iter() {
+            check_cycle(&open_map(cols, rows));
+        }
+        // An open rectangle inside walls.
+        check_cycle(
+            &map::parse("*****\n*@..*\n*...*\n*****\n").unwrap_or_else(|err| panic!("{}", err)),
+        );
+    }
+
+    #[test]
+    fn no_cycle() {
+        assert!(Cycle::build(&open_map(3, 3)).is_none());
+        assert!(Cycle::build(&open_map(5, 7)).is_none());
+        let notched =
+            map::parse("....\n..*.\n....\n..*.\n").unwrap_or_else(|err| panic!("{}", err));
+        assert!(Cycle::build(&notched).is_none());
+    }
+
+    #[test]
+    fn fills_open_map() {
+        for seed in 0..4 {
+            let terrain = open_map(6, 4);
+            let mut game = Game::new(
+                io::empty(),
+                io::sink(),
+                terrain.clone(),
+                seed,
+                Curve::classic(),
+            );
+            game.autopilot = Some(Autopilot::new(&terrain));
+            let (_, _, ending) = game.simulate();
+            assert_eq!(ending, Ending::Filled, "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Campaigns: maps played one after another, each with a goal that opens the
+//! next. Map paths are relative to the campaign file.
+//!
+//! ```text
+//! game-rs campaign v1
+//! level eat 10 open.txt
+//! level survive 30 portals.txt
+//! ```
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use super::map::{self, Map, MapError};
+
+const HEADER: &str = "game-rs campaign";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    // Food eaten on the level.
+    Eat(usize),
+    // Seconds played on the level, pauses aside.
+    Survive(u64),
+}
+
+impl Goal {
+    /// Parses `eat N` or `survive SECONDS`.
+    pub fn parse(s: &str) -> Option<Goal> {
+        let mut words = s.split_whitespace();
+        let goal = match (words.next(), words.next().map(|n| n.parse::<u64>())) {
+            (Some("eat"), Some(Ok(n))) if n > 0 => Goal::Eat(n as usize),
+            (Some("survive"), Some(Ok(secs))) if secs > 0 => Goal::Survive(secs),
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(goal),
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Goal::Eat(n) => write!(f, "eat {}", n),
+            Goal::Survive(secs) => write!(f, "survive {}s", secs),
+        }
+    }
+}
+
+pub struct Level {
+    pub map: Map,
+    pub goal: Goal,
+}
+
+pub struct Campaign {
+    pub levels: Vec<Level>,
+}
+
+#[derive(Debug)]
+pub enum CampaignError {
+    Io(io::Error),
+    Version(String),
+    Invalid {
+        line: usize,
+        reason: String,
+    },
+    Map {
+        line: usize,
+        path: String,
+        err: MapError,
+    },
+}
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CampaignError::Io(err) => write!(f, "{}", err),
+            CampaignError::Version(header) => write!(
+                f,
+                "unsupported campaign format {:?}, expected \"{} v{}\"",
+                header, HEADER, VERSION
+            ),
+            CampaignError::Invalid { line, reason } => write!(f, "line {}: {}", line, reason),
+            CampaignError::Map { line, path, err } => {
+                write!(f, "line {}: invalid map {}: {}", line, path, err)
+            }
+        }
+    }
+}
+
+impl error::Error for CampaignError {}
+
+impl From<io::Error> for CampaignError {
+    fn from(err: io::Error) -> Self {
+        CampaignError::Io(err)
+    }
+}
+
+impl Campaign {
+    /// Builds a campaign out of (goal, map) pairs, as `Goal::parse` and
+    /// `map::parse` take them.
+    pub fn from_levels(levels: &[(&str, &str)]) -> Result<Campaign, CampaignError> {
+        let mut campaign = Campaign { levels: Vec::new() };
+        for (i, (goal, content)) in levels.iter().enumerate() {
+            let goal = Goal::parse(goal).ok_or_else(|| invalid(i + 1, bad_goal(goal)))?;
+            let map = map::parse(content).map_err(|err| CampaignError::Map {
+                line: i + 1,
+                path: format!("#{}", i + 1),
+                err,
+            })?;
+            campaign.levels.push(Level { map, goal });
+        }
+        if campaign.levels.is_empty() {
+            return Err(invalid(1, "no levels".to_string()));
+        }
+        Ok(campaign)
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Campaign, CampaignError> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let file = fs::File::open(path)?;
+        let lines = BufReader::new(file)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let header = lines.first().map(|s| s.as_str()).unwrap_or("");
+        if header != format!("{} v{}", HEADER, VERSION) {
+            return Err(CampaignError::Version(header.to_string()));
+        }
+
+        let mut campaign = Campaign { levels: Vec::new() };
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            let lineno = i + 1;
+            let words: Vec<&str> = line.splitn(4, ' ').collect();
+            match words.as_slice() {
+                ["level", kind, value, map_path] => {
+                    let spec = format!("{} {}", kind, value);
+                    let goal =
+                        Goal::parse(&spec).ok_or_else(|| invalid(lineno, bad_goal(&spec)))?;
+                    let content = fs::read(dir.join(map_path))
+                        .map_err(|err| invalid(lineno, format!("{}: {}", map_path, err)))?;
+                    let map = map::decode(content)
+                        .and_then(|content| map::parse(&content))
+                        .map_err(|err| CampaignError::Map {
+                            line: lineno,
+                            path: map_path.to_string(),
+                            err,
+                        })?;
+                    campaign.levels.push(Level { map, goal });
+                }
+                [""] => {}
+                _ => return Err(invalid(lineno, format!("bad entry {:?}", line))),
+            }
+        }
+        if campaign.levels.is_empty() {
+            return Err(invalid(lines.len(), "no levels".to_string()));
+        }
+        Ok(campaign)
+    }
+}
+
+fn invalid(line: usize, reason: String) -> CampaignError {
+    CampaignError::Invalid { line, reason }
+}
+
+fn bad_goal(spec: &str) -> String {
+    format!(
+        "bad goal {:?}, expected \"eat N\" or \"survive SECONDS\"",
+        spec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    // Writes the campaign and its maps into a directory of their own.
+    fn write_campaign(name: &str, campaign: &str, maps: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("game-rs-campaign-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (path, content) in maps {
+            fs::write(dir.join(path), content).unwrap();
+        }
+        fs::write(dir.join("campaign.txt"), campaign).unwrap();
+        dir
+    }
+
+    fn read_campaign(dir: &Path) -> Result<Campaign, CampaignError> {
+        let campaign = Campaign::read(dir.join("campaign.txt"));
+        fs::remove_dir_all(dir).unwrap();
+        campaign
+    }
+
+    #[test]
+    fn parse_goal() {
+        assert_eq!(Goal::parse("eat 10"), Some(Goal::Eat(10)));
+        assert_eq!(Goal::parse("survive 30"), Some(Goal::Survive(30)));
+        assert_eq!(Goal::parse("eat 10 more"), None);
+        assert_eq!(Goal::parse("eat 0"), None);
+        assert_eq!(Goal::parse("survive 0"), None);
+        assert_eq!(Goal::parse("eat ten"), None);
+        assert_eq!(Goal::parse("survive -3"), None);
+        assert_eq!(Goal::parse("sleep 3"), None);
+        assert_eq!(Goal::parse("eat"), None);
+    }
+
+    #[test]
+    fn read_campaign_file() {
+        let dir = write_campaign(
+            "ok",
+            "game-rs campaign v1\nlevel eat 3 a.txt\n\nlevel survive 20 b.txt\n",
+            &[("a.txt", "@..\n...\n"), ("b.txt", "% edges solid\n.@.\n")],
+        );
+        let campaign = read_campaign(&dir).unwrap();
+        assert_eq!(campaign.levels.len(), 2);
+        assert_eq!(campaign.levels[0].goal, Goal::Eat(3));
+        assert_eq!(campaign.levels[1].goal, Goal::Survive(20));
+        assert_eq!(campaign.levels[1].map.edges, map::Edges::Solid);
+    }
+
+    #[test]
+    fn wrong_header() {
+        let dir = write_campaign("header", "game-rs campaign v2\n", &[]);
+        match read_campaign(&dir) {
+            Err(CampaignError::Version(header)) => assert_eq!(header, "game-rs campaign v2"),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn unknown_line() {
+        let dir = write_campaign(
+            "unknown",
+            "game-rs campaign v1\nlevel eat 3 a.txt\nbonus a.txt\n",
+            &[("a.txt", "@..\n")],
+        );
+        match read_campaign(&dir) {
+            Err(CampaignError::Invalid { line, .. }) => assert_eq!(line, 3),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn missing_map() {
+        let dir = write_campaign(
+            "missing",
+            "game-rs campaign v1\nlevel eat 3 gone.txt\n",
+            &[],
+        );
+        match read_campaign(&dir) {
+            Err(CampaignError::Invalid { line, reason }) => {
+                assert_eq!(line, 2);
+                assert!(reason.starts_with("gone.txt: "), "{}", reason);
+            }
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn bad_map() {
+        let dir = write_campaign(
+            "map",
+            "game-rs campaign v1\nlevel eat 3 a.txt\nlevel eat 3 b.txt\n",
+            &[("a.txt", "@..\n"), ("b.txt", "...\n.?.\n")],
+        );
+        match read_campaign(&dir) {
+            Err(CampaignError::Map { line, path, err }) => {
+                assert_eq!((line, path.as_str()), (3, "b.txt"));
+                assert_eq!(
+                    err,
+                    MapError::Mark {
+                        line: 2,
+                        col: 2,
+                        mark: '?'
+                    }
+                );
+            }
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn from_levels() {
+        match Campaign::from_levels(&[("eat 3", "@..\n"), ("eat 3", "..\n...\n")]) {
+            Err(CampaignError::Map { line, path, .. }) => {
+                assert_eq!((line, path.as_str()), (2, "#2"))
+            }
+            other => panic!("{:?}", other.err()),
+        }
+        match Campaign::from_levels(&[("eat three", "@..\n")]) {
+            Err(CampaignError::Invalid { line, .. }) => assert_eq!(line, 1),
+            other => panic!("{:?}", other.err()),
+        }
+        assert!(Campaign::from_levels(&[]).is_err());
+    }
+
+    #[test]
+    fn default_campaign() {
+        let campaign = Campaign::from_levels(&super::super::DEFAULT_CAMPAIGN)
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(campaign.levels.len(), super::super::DEFAULT_CAMPAIGN.len());
+    }
+}
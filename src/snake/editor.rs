@@ -0,0 +1,411 @@
+//! The map editor of `--edit`: the map drawn the way the game draws it, with
+//! a cursor to change it cell by cell, a test run of the game on it, and
+//! saving in the map file format.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::thread::sleep;
+
+use game_rs::rng;
+use termion::{clear, cursor, style};
+
+use super::input::{Decoder, Input};
+use super::map::{
+    self, Edges, BARRIER_MARK, DIRECTIVE_MARK, FOOD_MARK, ONE_WAY_MARK, SPACE_MARK, SPAWN_MARK,
+};
+use super::{draw_board, object, Curve, Game, Key, POLL_INTERVAL};
+
+// The size of a map started from scratch.
+const NEW_ROWS: usize = 15;
+const NEW_COLS: usize = 30;
+const MIN_SIZE: usize = 2;
+
+const HELP: [&str; 2] = [
+    "  move: arrows/hjkl  wall: space  marks: . * # o @ 0-9 A-Z",
+    "  size: < > - +  heading: d  one-way: u  edges: e  play: t  save: s  quit: q",
+];
+
+/// The map being edited, kept as the marks of the file format so that what
+/// is saved is what was typed, playable or not.
+pub struct Draft {
+    pub rows: usize,
+    pub cols: usize,
+    marks: Vec<char>,
+    direction: Option<Key>,
+    one_way: Key,
+    edges: Edges,
+}
+
+impl Draft {
+    pub fn new() -> Self {
+        Draft {
+            rows: NEW_ROWS,
+            cols: NEW_COLS,
+            marks: vec![SPACE_MARK; NEW_ROWS * NEW_COLS],
+            direction: None,
+            one_way: Key::Right,
+            edges: Edges::Wrap,
+        }
+    }
+
+    /// Reads a map file without judging it: short rows are padded, unknown
+    /// marks and directives dropped, for the editor to fix.
+    pub fn load(content: &str) -> Self {
+        let mut draft = Draft::new();
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            let directive = match line.strip_prefix(DIRECTIVE_MARK) {
+                Some(directive) => directive,
+                // Blank lines before the first row are not rows, as in
+                // `map::parse`.
+                None if lines.is_empty() && line.is_empty() => continue,
+                None => {
+                    lines.push(line);
+                    continue;
+                }
+            };
+            let words: Vec<&str> = directive.split_whitespace().collect();
+            match words.as_slice() {
+                ["direction", dir] => draft.direction = Key::parse_direction(dir),
+                ["oneway", dir] => draft.one_way = Key::parse_direction(dir).unwrap_or(Key::Right),
+                ["edges", mode] => draft.edges = Edges::parse(mode).unwrap_or(Edges::Wrap),
+                _ => {}
+            }
+        }
+        if lines.is_empty() {
+            return draft;
+        }
+
+        draft.rows = lines.len();
+        draft.cols = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(MIN_SIZE);
+        draft.marks.clear();
+        for line in lines {
+            let mut marks: Vec<char> = line
+                .chars()
+                .map(|mark| if is_mark(mark) { mark } else { SPACE_MARK })
+                .collect();
+            marks.resize(draft.cols, SPACE_MARK);
+            draft.marks.extend(marks);
+        }
+        draft
+    }
+
+    /// The draft in the map file format.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(direction) = self.direction {
+            text.push_str(&format!(
+                "{} direction {}\n",
+                DIRECTIVE_MARK,
+                direction.name()
+            ));
+        }
+        if self.one_way != Key::Right {
+            text.push_str(&format!(
+                "{} oneway {}\n",
+                DIRECTIVE_MARK,
+                self.one_way.name()
+            ));
+        }
+        if self.edges != Edges::Wrap {
+            text.push_str(&format!("{} edges {}\n", DIRECTIVE_MARK, self.edges.name()));
+        }
+        for row in self.marks.chunks(self.cols) {
+            text.extend(row.iter());
+            text.push('\n');
+        }
+        text
+    }
+
+    // Puts a mark on a cell; there is only one spawn point, so a new one
+    // moves it.
+    fn put(&mut self, pos: usize, mark: char) {
+        if mark == SPAWN_MARK {
+            for cell in self.marks.iter_mut().filter(|cell| **cell == SPAWN_MARK) {
+                *cell = SPACE_MARK;
+            }
+        }
+        self.marks[pos] = mark;
+    }
+
+    // Grows or shrinks the map at its right and bottom edges.
+    fn resize(&mut self, rows: usize, cols: usize) {
+        let mut marks = vec![SPACE_MARK; rows * cols];
+        for row in 0..rows.min(self.rows) {
+            for col in 0..cols.min(self.cols) {
+                marks[row * cols + col] = self.marks[row * self.cols + col];
+            }
+        }
+        self.marks = marks;
+        self.rows = rows;
+        self.cols = cols;
+    }
+
+    // The marks as the game draws them: the spawn point as the snake's head,
+    // food that grows back as that food.
+    fn cells(&self) -> Vec<object::Object> {
+        self.marks
+            .iter()
+            .map(|mark| match *mark {
+                BARRIER_MARK => object::BARRIER,
+                ONE_WAY_MARK => object::ONE_WAY,
+                SPAWN_MARK => object::SNAKE_HEAD,
+                FOOD_MARK => object::FIXED_FOOD,
+                '0'..='9' | 'A'..='Z' => object::PORTAL,
+                _ => object::SPACE,
+            })
+            .collect()
+    }
+}
+
+fn is_mark(c: char) -> bool {
+    matches!(
+        c,
+        SPACE_MARK | BARRIER_MARK | SPAWN_MARK | FOOD_MARK | ONE_WAY_MARK | '0'..='9' | 'A'..='Z'
+    )
+}
+
+pub struct Editor<R, W: Write> {
+    draft: Draft,
+    cursor: usize,
+    // The largest map the terminal has room for.
+    max_rows: usize,
+    max_cols: usize,
+    path: String,
+    // Whether there are changes not saved yet, and whether quit was pressed
+    // once over them; pressing it again drops them.
+    dirty: bool,
+    quitting: bool,
+    status: String,
+    curve: Curve,
+    stdin: R,
+    stdout: W,
+    input: Decoder,
+}
+
+impl<R: Read, W: Write> Editor<R, W> {
+    pub fn new(
+        stdin: R,
+        stdout: W,
+        path: String,
+        draft: Draft,
+        curve: Curve,
+        (max_rows, max_cols): (usize, usize),
+    ) -> Self {
+        // Say up front what keeps a loaded map from being played.
+        let status = match map::parse(&draft.to_text()) {
+            Ok(_) => String::new(),
+            Err(err) => err.to_string(),
+        };
+        Editor {
+            cursor: draft.marks.len() / 2,
+            draft,
+            max_rows,
+            max_cols,
+            path,
+            dirty: false,
+            quitting: false,
+            status,
+            curve,
+            stdin,
+            stdout,
+            input: Decoder::new(),
+        }
+    }
+
+    pub fn run(&mut self) {
+        let mut keys_buf = [0u8; 23];
+        self.draw();
+        loop {
+            let keys_num = self.stdin.read(&mut keys_buf).unwrap();
+            let inputs = self.input.feed(&keys_buf[..keys_num]);
+            if inputs.is_empty() {
+                sleep(POLL_INTERVAL);
+                continue;
+            }
+            for input in inputs {
+                if !self.handle(input) {
+                    return;
+                }
+            }
+            self.draw();
+        }
+    }
+
+    // Applies one key; returns false to quit.
+    fn handle(&mut self, input: Input) -> bool {
+        let quitting = self.quitting;
+        self.quitting = false;
+        let (rows, cols) = (self.draft.rows, self.draft.cols);
+        match input {
+            Input::Up | Input::Char(b'k') => self.move_cursor(Key::Up),
+            Input::Down | Input::Char(b'j') => self.move_cursor(Key::Down),
+            Input::Left | Input::Char(b'h') => self.move_cursor(Key::Left),
+            Input::Right | Input::Char(b'l') => self.move_cursor(Key::Right),
+            Input::Char(b' ') => {
+                let mark = if self.draft.marks[self.cursor] == BARRIER_MARK {
+                    SPACE_MARK
+                } else {
+                    BARRIER_MARK
+                };
+                self.edit(|draft, pos| draft.put(pos, mark));
+            }
+            Input::Char(key_byte) if is_mark(key_byte as char) => {
+                self.edit(|draft, pos| draft.put(pos, key_byte as char));
+            }
+            Input::Char(b'>') if cols < self.max_cols => self.resize(rows, cols + 1),
+            Input::Char(b'<') if cols > MIN_SIZE => self.resize(rows, cols - 1),
+            Input::Char(b'+') if rows < self.max_rows => self.resize(rows + 1, cols),
+            Input::Char(b'-') if rows > MIN_SIZE => self.resize(rows - 1, cols),
+            Input::Char(b'd') => {
+                let direction = match self.draft.direction {
+                    None => Some(Key::Up),
+                    Some(Key::Up) => Some(Key::Right),
+                    Some(Key::Right) => Some(Key::Down),
+                    Some(Key::Down) => Some(Key::Left),
+                    Some(_) => None,
+                };
+                self.edit(|draft, _| draft.direction = direction);
+            }
+            Input::Char(b'u') => {
+                let one_way = match self.draft.one_way {
+                    Key::Up => Key::Right,
+                    Key::Right => Key::Down,
+                    Key::Down => Key::Left,
+                    _ => Key::Up,
+                };
+                self.edit(|draft, _| draft.one_way = one_way);
+            }
+            Input::Char(b'e') => {
+                let edges = match self.draft.edges {
+                    Edges::Wrap => Edges::Solid,
+                    Edges::Solid => Edges::WrapRows,
+                    Edges::WrapRows => Edges::WrapCols,
+                    Edges::WrapCols => Edges::Wrap,
+                };
+                self.edit(|draft, _| draft.edges = edges);
+            }
+            Input::Char(b't') => self.test_play(),
+            Input::Char(b's') => self.save(),
+            Input::Char(b'q') | Input::Esc => {
+                if !self.dirty || quitting {
+                    return false;
+                }
+                self.quitting = true;
+                self.status = "unsaved changes, q again to drop them".to_string();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn move_cursor(&mut self, dir: Key) {
+        let cols = self.draft.cols;
+        let (row, col) = (self.cursor / cols, self.cursor % cols);
+        let (row, col) = match dir {
+            Key::Up => (row.saturating_sub(1), col),
+            Key::Down => ((row + 1).min(self.draft.rows - 1), col),
+            Key::Left => (row, col.saturating_sub(1)),
+            Key::Right => (row, (col + 1).min(cols - 1)),
+            _ => (row, col),
+        };
+        self.cursor = row * cols + col;
+    }
+
+    fn edit<F: FnOnce(&mut Draft, usize)>(&mut self, change: F) {
+        change(&mut self.draft, self.cursor);
+        self.dirty = true;
+        self.status.clear();
+    }
+
+    fn resize(&mut self, rows: usize, cols: usize) {
+        let (row, col) = (self.cursor / self.draft.cols, self.cursor % self.draft.cols);
+        self.edit(|draft, _| draft.resize(rows, cols));
+        self.cursor = row.min(rows - 1) * cols + col.min(cols - 1);
+    }
+
+    // Plays the map as it stands, back to the editor once the game quits.
+    fn test_play(&mut self) {
+        match map::parse(&self.draft.to_text()) {
+            Ok(terrain) => {
+                let seed = rng::random_seed();
+                let mut game =
+                    Game::new(&mut self.stdin, &mut self.stdout, terrain, seed, self.curve);
+                game.start();
+            }
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    // Saves even a map that can not be played yet, saying what is wrong.
+    fn save(&mut self) {
+        let text = self.draft.to_text();
+        self.status = match fs::write(&self.path, &text) {
+            Ok(()) => {
+                self.dirty = false;
+                match map::parse(&text) {
+                    Ok(_) => format!("saved {}", self.path),
+                    Err(err) => format!("saved, but {}", err),
+                }
+            }
+            Err(err) => format!("not saved: {}", err),
+        };
+    }
+
+    fn draw(&mut self) {
+        let draft = &self.draft;
+        let title = format!("EDIT {}{}", self.path, if self.dirty { "*" } else { "" });
+        let heading = draft.direction.map_or("any", |dir| dir.name());
+        let info = format!(
+            "start: {} edges: {} {}x{} ",
+            heading,
+            draft.edges.name(),
+            draft.cols,
+            draft.rows
+        );
+        let glyph = |pos: usize| match draft.marks[pos] {
+            ONE_WAY_MARK => draft.one_way.arrow(),
+            mark @ '0'..='9' | mark @ 'A'..='Z' => mark,
+            _ => ' ',
+        };
+        draw_board(
+            &mut self.stdout,
+            (&title, &info),
+            &draft.cells(),
+            draft.cols,
+            draft.edges,
+            &glyph,
+            Some(self.cursor),
+        );
+        write!(
+            self.stdout,
+            "{}  {}{}\n\r",
+            style::Bold,
+            self.status,
+            style::Reset
+        )
+        .unwrap();
+        for line in HELP.iter() {
+            write!(self.stdout, "{}\n\r", line).unwrap();
+        }
+        self.stdout.flush().unwrap();
+    }
+}
+
+impl<R, W: Write> Drop for Editor<R, W> {
+    fn drop(&mut self) {
+        write!(
+            self.stdout,
+            "{}{}{}",
+            clear::All,
+            style::Reset,
+            cursor::Goto(1, 1)
+        )
+        .unwrap();
+    }
+}
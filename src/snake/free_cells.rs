@@ -0,0 +1,143 @@
+//! The empty cells of the board, kept up to date as the snake moves so food
+//! goes on a random one in constant time, however full the board is.
+
+use rand::Rng;
+
+pub struct FreeCells {
+    cells: Vec<usize>,
+    // Where each cell of the board sits in `cells`, if it is free.
+    slots: Vec<Option<usize>>,
+}
+
+impl FreeCells {
+    pub fn new() -> Self {
+        FreeCells {
+            cells: Vec::new(),
+            slots: Vec::new(),
+        }
+    }
+
+    /// Empties the set for a board of `size` cells.
+    pub fn clear(&mut self, size: usize) {
+        self.cells.clear();
+        self.slots.clear();
+        self.slots.resize(size, None);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn insert(&mut self, pos: usize) {
+        if self.slots[pos].is_none() {
+            self.slots[pos] = Some(self.cells.len());
+            self.cells.push(pos);
+        }
+    }
+
+    pub fn remove(&mut self, pos: usize) {
+        if let Some(slot) = self.slots[pos].take() {
+            self.cells.swap_remove(slot);
+            // The last cell took the removed one's slot.
+            if let Some(&moved) = self.cells.get(slot) {
+                self.slots[moved] = Some(slot);
+            }
+        }
+    }
+
+    /// A free cell, every one as likely as the others.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<usize> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        Some(self.cells[rng.gen_range(0, self.cells.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_rs::rng::SplitMix64;
+
+    fn sorted(free: &FreeCells) -> Vec<usize> {
+        let mut cells = free.cells.clone();
+        cells.sort();
+        cells
+    }
+
+    // Every free cell sits in the slot its board cell points at.
+    fn check_slots(free: &FreeCells) {
+        for (slot, pos) in free.cells.iter().enumerate() {
+            assert_eq!(free.slots[*pos], Some(slot));
+        }
+        let taken = free.slots.iter().filter(|slot| slot.is_some()).count();
+        assert_eq!(taken, free.cells.len());
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut free = FreeCells::new();
+        free.clear(10);
+        assert!(free.is_empty());
+        for pos in [1, 3, 5, 7].iter() {
+            free.insert(*pos);
+        }
+        check_slots(&free);
+
+        // The last one, then one from the middle.
+        free.remove(7);
+        check_slots(&free);
+        assert_eq!(sorted(&free), vec![1, 3, 5]);
+        free.remove(3);
+        check_slots(&free);
+        assert_eq!(sorted(&free), vec![1, 5]);
+
+        // Removing a cell that is not free changes nothing.
+        free.remove(3);
+        free.remove(0);
+        check_slots(&free);
+        assert_eq!(sorted(&free), vec![1, 5]);
+
+        free.remove(1);
+        free.remove(5);
+        check_slots(&free);
+        assert!(free.is_empty());
+    }
+
+    #[test]
+    fn insert_twice() {
+        let mut free = FreeCells::new();
+        free.clear(4);
+        free.insert(2);
+        free.insert(2);
+        check_slots(&free);
+        assert_eq!(sorted(&free), vec![2]);
+        free.remove(2);
+        assert!(free.is_empty());
+    }
+
+    #[test]
+    fn sample_picks_free_cells() {
+        let mut free = FreeCells::new();
+        let mut rng = SplitMix64::new(42);
+        free.clear(100);
+        assert_eq!(free.sample(&mut rng), None);
+        // Every third cell goes in, and every ninth out again.
+        let mut inserted = [false; 100];
+        for pos in (0..100).step_by(3) {
+            free.insert(pos);
+            inserted[pos] = true;
+        }
+        for pos in (0..100).step_by(9) {
+            free.remove(pos);
+            inserted[pos] = false;
+        }
+        let mut seen = [false; 100];
+        for _ in 0..2000 {
+            let pos = free.sample(&mut rng).unwrap();
+            assert!(inserted[pos]);
+            seen[pos] = true;
+        }
+        assert_eq!(seen.iter().filter(|seen| **seen).count(), free.cells.len());
+    }
+}
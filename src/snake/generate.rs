@@ -0,0 +1,349 @@
+//! Random maps for `--generate`, written out in the map file format. Every
+//! empty cell of a generated map can be reached from the spawn point, and the
+//! cells around the spawn point and ahead of it are clear, whichever way the
+//! edges go.
+
+use std::collections::VecDeque;
+
+use game_rs::rng::SplitMix64;
+use rand::Rng;
+
+use super::map::{Edges, BARRIER_MARK, DIRECTIVE_MARK, SPACE_MARK, SPAWN_MARK};
+
+pub const MIN_SIZE: usize = 8;
+pub const MAX_SIZE: usize = 256;
+// How many cells around the spawn point are kept clear, and how many ahead
+// of it, where the snake heads first.
+const CLEAR_RADIUS: usize = 2;
+const RUNWAY: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // Walls and short wall segments strewn over an open field.
+    Scatter,
+    // Rooms joined by corridors, walls everywhere else.
+    Rooms,
+    // A maze with some of its dead ends knocked through.
+    Maze,
+}
+
+impl Kind {
+    pub fn parse(s: &str) -> Option<Kind> {
+        match s {
+            "scatter" => Some(Kind::Scatter),
+            "rooms" => Some(Kind::Rooms),
+            "maze" => Some(Kind::Maze),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Scatter => "scatter",
+            Kind::Rooms => "rooms",
+            Kind::Maze => "maze",
+        }
+    }
+}
+
+struct Grid {
+    walls: Vec<bool>,
+    cols: usize,
+    rows: usize,
+    edges: Edges,
+}
+
+/// Builds a `cols` x `rows` map; the same seed builds the same map.
+pub fn generate(kind: Kind, cols: usize, rows: usize, edges: Edges, seed: u64) -> String {
+    let mut rng = SplitMix64::new(seed);
+    let mut grid = Grid {
+        walls: vec![kind != Kind::Scatter; cols * rows],
+        cols,
+        rows,
+        edges,
+    };
+    let spawn = match kind {
+        Kind::Scatter => grid.scatter(&mut rng),
+        Kind::Rooms => grid.rooms(&mut rng),
+        Kind::Maze => grid.maze(&mut rng),
+    };
+    grid.clear_around(spawn);
+    grid.wall_off_unreachable(spawn);
+    grid.to_text(spawn)
+}
+
+impl Grid {
+    fn scatter(&mut self, rng: &mut SplitMix64) -> usize {
+        let cells = self.walls.len();
+        for _ in 0..cells / 12 {
+            let pos = rng.gen_range(0, cells);
+            self.walls[pos] = true;
+        }
+        for _ in 0..cells / 60 {
+            let (row, col) = (rng.gen_range(0, self.rows), rng.gen_range(0, self.cols));
+            let len = rng.gen_range(2, 7);
+            let across = rng.gen::<bool>();
+            for i in 0..len {
+                let (row, col) = if across {
+                    (row, col + i)
+                } else {
+                    (row + i, col)
+                };
+                if row < self.rows && col < self.cols {
+                    self.walls[row * self.cols + col] = true;
+                }
+            }
+        }
+        (self.rows / 2) * self.cols + self.cols / 2
+    }
+
+    fn rooms(&mut self, rng: &mut SplitMix64) -> usize {
+        let max_rooms = (self.walls.len() / 60).clamp(2, 12);
+        // (row, col, height, width)
+        let mut rooms: Vec<(usize, usize, usize, usize)> = Vec::new();
+        for _ in 0..max_rooms * 8 {
+            if rooms.len() == max_rooms {
+                break;
+            }
+            let height = rng.gen_range(3, (self.rows - 2).min(6) + 1);
+            let width = rng.gen_range(4, (self.cols - 2).min(10) + 1);
+            let row = rng.gen_range(1, self.rows - height);
+            let col = rng.gen_range(1, self.cols - width);
+            // Rooms keep a wall between them.
+            let overlaps = rooms.iter().any(|&(r, c, h, w)| {
+                row <= r + h && r <= row + height && col <= c + w && c <= col + width
+            });
+            if overlaps {
+                continue;
+            }
+            for r in row..row + height {
+                for c in col..col + width {
+                    self.walls[r * self.cols + c] = false;
+                }
+            }
+            rooms.push((row, col, height, width));
+        }
+
+        let centers: Vec<(usize, usize)> = rooms
+            .iter()
+            .map(|&(r, c, h, w)| (r + h / 2, c + w / 2))
+            .collect();
+        for pair in centers.windows(2) {
+            let ((r1, c1), (r2, c2)) = (pair[0], pair[1]);
+            // An L-shaped corridor, bending one way or the other.
+            let bend = if rng.gen::<bool>() {
+                (r1, c2)
+            } else {
+                (r2, c1)
+            };
+            self.carve_line((r1, c1), bend);
+            self.carve_line(bend, (r2, c2));
+        }
+        let (row, col) = centers[0];
+        row * self.cols + col
+    }
+
+    fn maze(&mut self, rng: &mut SplitMix64) -> usize {
+        // Passages run through the cells with odd coordinates, the walls
+        // between them are knocked out as the maze is carved.
+        let (cell_rows, cell_cols) = ((self.rows - 1) / 2, (self.cols - 1) / 2);
+        let cols = self.cols;
+        let at = move |r: usize, c: usize| (2 * r + 1) * cols + 2 * c + 1;
+        let mut visited = vec![false; cell_rows * cell_cols];
+        let start = (cell_rows / 2, cell_cols / 2);
+        let mut stack = vec![start];
+        visited[start.0 * cell_cols + start.1] = true;
+        self.walls[at(start.0, start.1)] = false;
+        while let Some(&(r, c)) = stack.last() {
+            let mut next = Vec::with_capacity(4);
+            if r > 0 {
+                next.push((r - 1, c));
+            }
+            if r + 1 < cell_rows {
+                next.push((r + 1, c));
+            }
+            if c > 0 {
+                next.push((r, c - 1));
+            }
+            if c + 1 < cell_cols {
+                next.push((r, c + 1));
+            }
+            next.retain(|&(nr, nc)| !visited[nr * cell_cols + nc]);
+            if next.is_empty() {
+                stack.pop();
+                continue;
+            }
+            let (nr, nc) = next[rng.gen_range(0, next.len())];
+            visited[nr * cell_cols + nc] = true;
+            self.walls[at(nr, nc)] = false;
+            self.walls[(at(r, c) + at(nr, nc)) / 2] = false;
+            stack.push((nr, nc));
+        }
+
+        // A perfect maze is all dead ends for a snake; knock through some of
+        // the walls between two passages.
+        for row in 1..self.rows - 1 {
+            for col in 1..self.cols - 1 {
+                let pos = row * self.cols + col;
+                let across = !self.walls[pos - 1] && !self.walls[pos + 1];
+                let down = !self.walls[pos - self.cols] && !self.walls[pos + self.cols];
+                if self.walls[pos] && (across || down) && rng.gen_range(0, 8) == 0 {
+                    self.walls[pos] = false;
+                }
+            }
+        }
+        at(start.0, start.1)
+    }
+
+    fn carve_line(&mut self, (r1, c1): (usize, usize), (r2, c2): (usize, usize)) {
+        for r in r1.min(r2)..=r1.max(r2) {
+            for c in c1.min(c2)..=c1.max(c2) {
+                self.walls[r * self.cols + c] = false;
+            }
+        }
+    }
+
+    // Clears the square around the spawn point and the runway to its right,
+    // where the snake heads first.
+    fn clear_around(&mut self, spawn: usize) {
+        let (row, col) = (spawn / self.cols, spawn % self.cols);
+        let rows = row.saturating_sub(CLEAR_RADIUS)..(row + CLEAR_RADIUS + 1).min(self.rows);
+        let cols = col.saturating_sub(CLEAR_RADIUS)..(col + CLEAR_RADIUS + 1).min(self.cols);
+        for r in rows {
+            for c in cols.clone() {
+                self.walls[r * self.cols + c] = false;
+            }
+        }
+        for c in col..(col + RUNWAY + 1).min(self.cols) {
+            self.walls[row * self.cols + c] = false;
+        }
+    }
+
+    // Turns every empty cell the snake can not get to from `spawn` into a
+    // wall, moving across the edges as the game does.
+    fn wall_off_unreachable(&mut self, spawn: usize) {
+        let (rows, cols) = (self.rows, self.cols);
+        let mut seen = vec![false; self.walls.len()];
+        let mut queue = VecDeque::new();
+        seen[spawn] = true;
+        queue.push_back(spawn);
+        while let Some(pos) = queue.pop_front() {
+            let (row, col) = (pos / cols, pos % cols);
+            let (wraps_rows, wraps_cols) = (self.edges.wraps_rows(), self.edges.wraps_cols());
+            let mut neighbours = Vec::with_capacity(4);
+            if row > 0 || wraps_cols {
+                neighbours.push(((row + rows - 1) % rows) * cols + col);
+            }
+            if row + 1 < rows || wraps_cols {
+                neighbours.push(((row + 1) % rows) * cols + col);
+            }
+            if col > 0 || wraps_rows {
+                neighbours.push(row * cols + (col + cols - 1) % cols);
+            }
+            if col + 1 < cols || wraps_rows {
+                neighbours.push(row * cols + (col + 1) % cols);
+            }
+            for next in neighbours.iter() {
+                if !self.walls[*next] && !seen[*next] {
+                    seen[*next] = true;
+                    queue.push_back(*next);
+                }
+            }
+        }
+        for (wall, seen) in self.walls.iter_mut().zip(seen) {
+            *wall = *wall || !seen;
+        }
+    }
+
+    fn to_text(&self, spawn: usize) -> String {
+        let mut text = format!("{} direction right\n", DIRECTIVE_MARK);
+        if self.edges != Edges::Wrap {
+            text.push_str(&format!("{} edges {}\n", DIRECTIVE_MARK, self.edges.name()));
+        }
+        for (pos, wall) in self.walls.iter().enumerate() {
+            text.push(if pos == spawn {
+                SPAWN_MARK
+            } else if *wall {
+                BARRIER_MARK
+            } else {
+                SPACE_MARK
+            });
+            if (pos + 1) % self.cols == 0 {
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::map;
+    use super::super::object;
+    use super::*;
+
+    const KINDS: [Kind; 3] = [Kind::Scatter, Kind::Rooms, Kind::Maze];
+    const EDGES: [Edges; 4] = [Edges::Wrap, Edges::Solid, Edges::WrapRows, Edges::WrapCols];
+
+    #[test]
+    fn generated_maps() {
+        for &kind in KINDS.iter() {
+            for &edges in EDGES.iter() {
+                for &(cols, rows) in [(MIN_SIZE, MIN_SIZE), (40, 20), (17, 31)].iter() {
+                    for seed in 0..8 {
+                        let text = generate(kind, cols, rows, edges, seed);
+                        let what = format!(
+                            "{} {:?} {}x{} seed {}",
+                            kind.name(),
+                            edges,
+                            cols,
+                            rows,
+                            seed
+                        );
+                        let map =
+                            map::parse(&text).unwrap_or_else(|err| panic!("{}: {}", what, err));
+                        assert_eq!(
+                            (map.cols, map.rows(), map.edges),
+                            (cols, rows, edges),
+                            "{}",
+                            what
+                        );
+
+                        let reachable = map.reachable(map.start());
+                        for (pos, cell) in map.cells.iter().enumerate() {
+                            if *cell == object::SPACE {
+                                assert!(reachable[pos], "{}: cell {} is cut off", what, pos);
+                            }
+                        }
+
+                        let (row, col) = (map.start() / cols, map.start() % cols);
+                        let clear = |r: usize, c: usize| {
+                            assert_eq!(
+                                map.cells[r * cols + c],
+                                object::SPACE,
+                                "{}: {},{} is not clear",
+                                what,
+                                r,
+                                c
+                            );
+                        };
+                        for r in
+                            row.saturating_sub(CLEAR_RADIUS)..(row + CLEAR_RADIUS + 1).min(rows)
+                        {
+                            for c in
+                                col.saturating_sub(CLEAR_RADIUS)..(col + CLEAR_RADIUS + 1).min(cols)
+                            {
+                                clear(r, c);
+                            }
+                        }
+                        for c in col..(col + RUNWAY + 1).min(cols) {
+                            clear(row, c);
+                        }
+
+                        assert_eq!(generate(kind, cols, rows, edges, seed), text, "{}", what);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Turns the raw bytes of the async reader into keys, arrow keys included.
+//! Escape sequences may be split across reads, so an unfinished one waits for
+//! the next read; a lone ESC is only known to be the Esc key once a read
+//! comes back empty.
+//!
+//! Terminals that support focus reporting also send ESC [ I and ESC [ O when
+//! they gain or lose focus; `FOCUS_ON` turns that on.
+
+use std::mem;
+
+const ESC: u8 = 0x1b;
+// Longer "sequences" are garbage, not keys.
+const MAX_PENDING: usize = 16;
+
+pub const FOCUS_ON: &str = "\x1b[?1004h";
+pub const FOCUS_OFF: &str = "\x1b[?1004l";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Char(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Esc,
+    FocusIn,
+    FocusOut,
+}
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    /// Decodes the bytes of one read. An empty read flushes a pending ESC as
+    /// the Esc key and drops an unfinished sequence.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        if bytes.is_empty() {
+            if self.pending == [ESC] {
+                inputs.push(Input::Esc);
+            }
+            self.pending.clear();
+            return inputs;
+        }
+
+        let mut buf = mem::take(&mut self.pending);
+        buf.extend_from_slice(bytes);
+        let mut i = 0;
+        while i < buf.len() {
+            if buf[i] != ESC {
+                inputs.push(Input::Char(buf[i]));
+                i += 1;
+                continue;
+            }
+            match buf.get(i + 1) {
+                None => {
+                    self.pending.push(ESC);
+                    break;
+                }
+                Some(&prefix @ b'[') | Some(&prefix @ b'O') => match sequence_end(&buf[i + 2..]) {
+                    Some(len) => {
+                        let end = i + 2 + len;
+                        if let Some(input) = sequence(prefix, &buf[i + 2..end]) {
+                            inputs.push(input);
+                        }
+                        i = end;
+                    }
+                    None => {
+                        if buf.len() - i <= MAX_PENDING {
+                            self.pending.extend_from_slice(&buf[i..]);
+                        }
+                        break;
+                    }
+                },
+                // ESC before anything else is the Esc key on its own.
+                Some(_) => {
+                    inputs.push(Input::Esc);
+                    i += 1;
+                }
+            }
+        }
+        inputs
+    }
+}
+
+// The length of a CSI/SS3 body up to and including its final byte, `None` if
+// the final byte has not arrived yet.
+fn sequence_end(body: &[u8]) -> Option<usize> {
+    body.iter()
+        .position(|b| (0x40..=0x7e).contains(b))
+        .map(|i| i + 1)
+}
+
+// The key of a whole sequence, `None` for the ones the games have no use for.
+// Arrows may carry modifiers, as in ESC [ 1 ; 5 A.
+fn sequence(prefix: u8, body: &[u8]) -> Option<Input> {
+    match (prefix, body) {
+        (b'[', b"I") => Some(Input::FocusIn),
+        (b'[', b"O") => Some(Input::FocusOut),
+        (_, [.., b'A']) => Some(Input::Up),
+        (_, [.., b'B']) => Some(Input::Down),
+        (_, [.., b'C']) => Some(Input::Right),
+        (_, [.., b'D']) => Some(Input::Left),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_sequence() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(b"\x1b["), []);
+        assert_eq!(decoder.feed(b"A"), [Input::Up]);
+    }
+
+    #[test]
+    fn lone_esc() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(b"\x1b"), []);
+        assert_eq!(decoder.feed(b""), [Input::Esc]);
+        assert_eq!(decoder.feed(b""), []);
+    }
+
+    #[test]
+    fn modified_arrow() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.feed(b"\x1b[1;5Aq\x1bOD"),
+            [Input::Up, Input::Char(b'q'), Input::Left]
+        );
+    }
+
+    #[test]
+    fn garbage_dropped() {
+        let mut decoder = Decoder::new();
+        let mut garbage = b"\x1b[".to_vec();
+        garbage.extend_from_slice(&[b'1'; MAX_PENDING]);
+        assert_eq!(decoder.feed(&garbage), []);
+        assert_eq!(decoder.feed(b"A"), [Input::Char(b'A')]);
+    }
+
+    #[test]
+    fn focus() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.feed(b"\x1b[I\x1b[O"),
+            [Input::FocusIn, Input::FocusOut]
+        );
+    }
+}
@@ -1,15 +1,40 @@
+extern crate game_rs;
 extern crate rand;
 extern crate termion;
 
+mod autopilot;
+mod campaign;
+mod editor;
+mod free_cells;
+mod generate;
+mod input;
+mod map;
+mod rival;
+mod screen;
+
 use std::collections::VecDeque;
 use std::env;
+use std::fmt;
 use std::fs;
-use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::io::{self, Read, Write};
+use std::path::Path;
 use std::process;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
+use autopilot::Autopilot;
+use campaign::{Campaign, Goal};
+use editor::{Draft, Editor};
+use free_cells::FreeCells;
+use game_rs::replay::{Playback, Replay, Speed};
+use game_rs::rng::{self, SplitMix64};
+use game_rs::scores::{self, NameInput, Scores};
+use generate::Kind;
+use input::{Decoder, Input};
+use map::{Edges, Map};
 use rand::Rng;
+use rival::{Respawn, Rivals, Skill, Strategy};
+use screen::Screen;
 use termion::raw::IntoRawMode;
 use termion::{async_stdin, clear, color, cursor, style};
 
@@ -22,21 +47,104 @@ mod object {
     pub const SNAKE_HEAD: Object = 2;
     pub const SNAKE_BODY: Object = 3;
     pub const FOOD: Object = 4;
+    // Teleports the head to the cell past its pair.
+    pub const PORTAL: Object = 5;
+    // A wall that can only be crossed in the map's one-way direction.
+    pub const ONE_WAY: Object = 6;
+    // Food that grows back on the same cell.
+    pub const FIXED_FOOD: Object = 7;
+    // Worth more, for a while.
+    pub const BONUS_FOOD: Object = 8;
+    pub const SHRINK_FOOD: Object = 9;
+    pub const FAST_FOOD: Object = 10;
+    pub const SLOW_FOOD: Object = 11;
+    pub const GOLDEN_FOOD: Object = 12;
+    // The second player's snake.
+    pub const SNAKE2_HEAD: Object = 13;
+    pub const SNAKE2_BODY: Object = 14;
+    // Rivals, by strategy.
+    pub const GREEDY_HEAD: Object = 15;
+    pub const GREEDY_BODY: Object = 16;
+    pub const BFS_HEAD: Object = 17;
+    pub const BFS_BODY: Object = 18;
+    pub const RANDOM_HEAD: Object = 19;
+    pub const RANDOM_BODY: Object = 20;
 
-    pub const COLORS: [color::Bg<color::Rgb>; 5] = [
+    pub const COLORS: [color::Bg<color::Rgb>; 21] = [
         color::Bg(color::Rgb(224, 224, 224)),
         color::Bg(color::Rgb(0, 0, 0)),
         color::Bg(color::Rgb(153, 0, 0)),
         color::Bg(color::Rgb(255, 0, 0)),
         color::Bg(color::Rgb(0, 153, 0)),
+        color::Bg(color::Rgb(153, 51, 255)),
+        color::Bg(color::Rgb(96, 96, 96)),
+        color::Bg(color::Rgb(0, 102, 0)),
+        color::Bg(color::Rgb(0, 204, 204)),
+        color::Bg(color::Rgb(255, 153, 204)),
+        color::Bg(color::Rgb(255, 128, 0)),
+        color::Bg(color::Rgb(0, 102, 204)),
+        color::Bg(color::Rgb(255, 204, 0)),
+        color::Bg(color::Rgb(102, 0, 102)),
+        color::Bg(color::Rgb(204, 0, 204)),
+        color::Bg(color::Rgb(102, 51, 0)),
+        color::Bg(color::Rgb(153, 102, 51)),
+        color::Bg(color::Rgb(0, 0, 102)),
+        color::Bg(color::Rgb(51, 51, 153)),
+        color::Bg(color::Rgb(102, 102, 0)),
+        color::Bg(color::Rgb(153, 153, 51)),
+    ];
+
+    /// The food that is spread over the map: each kind with how often it
+    /// turns up, and its name on the legend.
+    pub const FOODS: [(Object, u32, &str); 6] = [
+        (FOOD, 70, "+1"),
+        (BONUS_FOOD, 10, "bonus"),
+        (SHRINK_FOOD, 8, "shrink"),
+        (FAST_FOOD, 5, "fast"),
+        (SLOW_FOOD, 5, "slow"),
+        (GOLDEN_FOOD, 2, "golden"),
     ];
+
+    pub fn is_food(obj: Object) -> bool {
+        obj == FIXED_FOOD || FOODS.iter().any(|&(food, _, _)| food == obj)
+    }
 }
 
-// TODO(damnever): make speed and game map configurable.
-const SPACE_MARK: char = '.';
-const BARRIER_MARK: char = '*';
 const MAX_FOOD: usize = 10;
-const SPEEDS: [Duration; 10] = [
+// Bonus food is worth more and goes off after a while, in ticks.
+const BONUS_POINTS: usize = 5;
+const BONUS_TICKS: u64 = 60;
+const GOLDEN_POINTS: usize = 20;
+// How many cells shrinking food takes off the tail.
+const SHRINK_CELLS: usize = 3;
+// How long fast and slow food change the pace, in ticks.
+const BOOST_TICKS: u64 = 50;
+// How many turns may wait for their tick.
+const MAX_TURNS: usize = 3;
+// Draws the screen again from scratch.
+const CTRL_L: u8 = 0x0c;
+const DIRECTIONS: [Key; 4] = [Key::Up, Key::Right, Key::Down, Key::Left];
+// Players share the keyboard: the first one steers with WASD, the second one
+// with the arrows. Rounds won take a match.
+const MAX_PLAYERS: usize = 2;
+const ROUNDS_TO_WIN: usize = 3;
+const PLAYER_KEYS: [&str; MAX_PLAYERS] = ["wasd", "arrows"];
+// How many random cells a rival coming back looks at for one out in the open.
+const RIVAL_SPOT_TRIES: usize = 8;
+// Rivals turn up no nearer to a player's head than this many steps, nor on
+// this many cells ahead of it whichever way it turns.
+const RIVAL_CLEARANCE: usize = 3;
+const RIVAL_RUNWAY: usize = 8;
+// A benchmarked game that goes this many times the cells of the map without
+// eating is stuck.
+const STALL_LAPS: usize = 2;
+// How often a game held still, by a paused replay or the name prompt, looks
+// for keys.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const FAST_FORWARD: u32 = 8;
+// The ticks of the classic curve, a step faster every tenth of a third of
+// the map eaten.
+const CLASSIC_SPEEDS: [Duration; 10] = [
     Duration::from_millis(600),
     Duration::from_millis(500),
     Duration::from_millis(400),
@@ -48,7 +156,21 @@ const SPEEDS: [Duration; 10] = [
     Duration::from_millis(30),
     Duration::from_millis(11),
 ];
-const DEFAULT_MAP: &'static str = "**...............................................**
+const CLASSIC: &str = "classic";
+// (name, start tick, fastest tick, speed-up per food), in milliseconds.
+const DIFFICULTIES: [(&str, u64, u64, u64); 4] = [
+    ("easy", 250, 120, 2),
+    ("normal", 150, 60, 2),
+    ("hard", 100, 40, 3),
+    ("insane", 60, 25, 2),
+];
+const DEFAULT_DIFFICULTY: &str = CLASSIC;
+// The size of a `--generate`d map, unless `--size` says otherwise.
+const DEFAULT_GENERATE_COLS: usize = 51;
+const DEFAULT_GENERATE_ROWS: usize = 23;
+// Where `--edit` without a file saves the map.
+const DEFAULT_EDIT_PATH: &str = "map.txt";
+const DEFAULT_MAP: &str = "**...............................................**
 *.................................................*
 ...................................................
 ...................................................
@@ -72,8 +194,184 @@ const DEFAULT_MAP: &'static str = "**...........................................
 *.................................................*
 **...............................................**
 ";
+const BOX_MAP: &str = "*******************************
+*.............................*
+*.............................*
+*.........*.........*.........*
+*.........*.........*.........*
+*.........*.........*.........*
+*.........*.........*.........*
+*.........*....@....*.........*
+*.........*.........*.........*
+*.........*.........*.........*
+*.........*.........*.........*
+*.........*.........*.........*
+*.............................*
+*.............................*
+*******************************
+";
+const PORTAL_MAP: &str = "% direction right
+*******************************
+*.............................*
+*.......o......*..............*
+*...A..........*..........B...*
+*..............*..............*
+*..............*..............*
+*..............*..............*
+*...***********************...*
+*..............*..............*
+*..............*..............*
+*.......@......*..............*
+*...B..........*..........A...*
+*..............*......o.......*
+*.............................*
+*******************************
+";
+const ONE_WAY_MAP: &str = "% direction right
+% oneway right
+*******************************
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*...@.........................*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*.......#.......#.......#.....*
+*******************************
+";
+// The campaign played by `--campaign` without a file: (goal, map).
+const DEFAULT_CAMPAIGN: [(&str, &str); 4] = [
+    ("eat 5", DEFAULT_MAP),
+    ("eat 8", BOX_MAP),
+    ("survive 40", PORTAL_MAP),
+    ("eat 12", ONE_WAY_MAP),
+];
+
+/// How long a tick lasts as the snake eats: it starts at `start` and gets
+/// `accel` shorter with every food, down to `min`. The classic curve steps
+/// down `CLASSIC_SPEEDS` instead, from `start` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Curve {
+    start: Duration,
+    min: Duration,
+    accel: Duration,
+    classic: bool,
+}
+
+impl Curve {
+    fn new(start: u64, min: u64, accel: u64) -> Curve {
+        Curve {
+            start: Duration::from_millis(start),
+            // A start faster than the minimum just means no speed-up.
+            min: Duration::from_millis(min.min(start)),
+            accel: Duration::from_millis(accel),
+            classic: false,
+        }
+    }
 
-#[derive(Debug, Clone, Copy)]
+    fn classic() -> Curve {
+        Curve {
+            start: CLASSIC_SPEEDS[0],
+            min: CLASSIC_SPEEDS[CLASSIC_SPEEDS.len() - 1],
+            accel: Duration::from_millis(0),
+            classic: true,
+        }
+    }
+
+    fn difficulty(name: &str) -> Option<Curve> {
+        if name == CLASSIC {
+            return Some(Curve::classic());
+        }
+        DIFFICULTIES
+            .iter()
+            .find(|d| d.0 == name)
+            .map(|&(_, start, min, accel)| Curve::new(start, min, accel))
+    }
+
+    /// Parses `START,MIN,ACCEL` in milliseconds, or `classic,START` as
+    /// `Display` writes the classic curve.
+    fn parse(s: &str) -> Option<Curve> {
+        if let Some(start) = s.strip_prefix("classic,") {
+            return match start.trim().parse::<u64>() {
+                Ok(start) if start > 0 => Some(Curve::classic().with_start(start)),
+                _ => None,
+            };
+        }
+        let parts = s
+            .split(',')
+            .map(|part| part.trim().parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match parts.as_slice() {
+            [start, min, accel] if *start > 0 && *min > 0 => Some(Curve::new(*start, *min, *accel)),
+            _ => None,
+        }
+    }
+
+    fn with_start(self, start: u64) -> Curve {
+        if self.classic {
+            return Curve {
+                start: Duration::from_millis(start),
+                ..self
+            };
+        }
+        Curve::new(
+            start,
+            self.min.as_millis() as u64,
+            self.accel.as_millis() as u64,
+        )
+    }
+
+    // The tick after `food` eaten on a map of `cells` cells.
+    fn tick(&self, food: usize, cells: usize) -> Duration {
+        if self.classic {
+            let per_step = (cells / 3 / CLASSIC_SPEEDS.len()).max(1);
+            let step = (food / per_step).min(CLASSIC_SPEEDS.len() - 1);
+            return CLASSIC_SPEEDS[step].min(self.start);
+        }
+        let faster = self
+            .accel
+            .saturating_mul(food.min(u32::MAX as usize) as u32);
+        self.start.saturating_sub(faster).max(self.min)
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.classic {
+            return write!(f, "{},{}", CLASSIC, self.start.as_millis());
+        }
+        write!(
+            f,
+            "{},{},{}",
+            self.start.as_millis(),
+            self.min.as_millis(),
+            self.accel.as_millis()
+        )
+    }
+}
+
+// How a game played out by `Game::simulate` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ending {
+    Filled,
+    Crashed,
+    // Went round and round without eating.
+    Stuck,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boost {
+    Fast,
+    Slow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Key {
     Up,
     Down,
@@ -85,310 +383,1201 @@ enum Key {
 
 impl Key {
     fn from(key_byte: u8, default: Key) -> Key {
+        Key::parse(key_byte).unwrap_or(default)
+    }
+
+    fn from_input(input: Input) -> Option<Key> {
+        match input {
+            Input::Char(key_byte) => Key::parse(key_byte),
+            Input::Up => Some(Key::Up),
+            Input::Down => Some(Key::Down),
+            Input::Left => Some(Key::Left),
+            Input::Right => Some(Key::Right),
+            Input::Esc => Some(Key::Quit),
+            Input::FocusIn | Input::FocusOut => None,
+        }
+    }
+
+    fn parse(key_byte: u8) -> Option<Key> {
         let key = key_byte as char;
         match key {
-            'w' | 'k' => Key::Up,
-            'd' | 'l' => Key::Right,
-            's' | 'j' => Key::Down,
-            'a' | 'h' => Key::Left,
-            'r' => Key::Restart,
-            'q' => Key::Quit,
-            _ => default,
+            'w' | 'k' => Some(Key::Up),
+            'd' | 'l' => Some(Key::Right),
+            's' | 'j' => Some(Key::Down),
+            'a' | 'h' => Some(Key::Left),
+            'r' => Some(Key::Restart),
+            'q' => Some(Key::Quit),
+            _ => None,
+        }
+    }
+
+    // The byte a replay records for the key.
+    fn code(self) -> u8 {
+        match self {
+            Key::Up => b'w',
+            Key::Right => b'd',
+            Key::Down => b's',
+            Key::Left => b'a',
+            Key::Restart => b'r',
+            Key::Quit => b'q',
+        }
+    }
+
+    fn opposite(self) -> Option<Key> {
+        match self {
+            Key::Up => Some(Key::Down),
+            Key::Right => Some(Key::Left),
+            Key::Down => Some(Key::Up),
+            Key::Left => Some(Key::Right),
+            _ => None,
+        }
+    }
+
+    fn parse_direction(s: &str) -> Option<Key> {
+        match s {
+            "up" => Some(Key::Up),
+            "right" => Some(Key::Right),
+            "down" => Some(Key::Down),
+            "left" => Some(Key::Left),
+            _ => None,
         }
     }
 
-    fn rand_direction(rng: &mut rand::rngs::ThreadRng) -> Key {
+    // The name `parse_direction` reads back.
+    fn name(self) -> &'static str {
+        match self {
+            Key::Up => "up",
+            Key::Right => "right",
+            Key::Down => "down",
+            Key::Left => "left",
+            _ => "",
+        }
+    }
+
+    fn arrow(self) -> char {
+        match self {
+            Key::Up => '↑',
+            Key::Right => '→',
+            Key::Down => '↓',
+            Key::Left => '←',
+            _ => ' ',
+        }
+    }
+
+    fn rand_direction(rng: &mut SplitMix64) -> Key {
         let directions = [Key::Up, Key::Down, Key::Left, Key::Right];
         directions[rng.gen_range(0, 4)]
     }
 }
 
+struct Snake {
+    body: VecDeque<usize>,
+    heading: Key,
+    // Turns typed ahead of the ticks that will take them, one per tick.
+    turns: VecDeque<Key>,
+    score: usize,
+    // What its head and body are on the map.
+    looks: (object::Object, object::Object),
+    // How a rival steers, and the tick a dead one comes back; a snake with
+    // no body is dead.
+    brain: Option<Strategy>,
+    respawn_at: Option<u64>,
+}
+
+impl Snake {
+    fn new(looks: (object::Object, object::Object)) -> Snake {
+        Snake {
+            body: VecDeque::new(),
+            heading: Key::Right,
+            turns: VecDeque::with_capacity(MAX_TURNS),
+            score: 0,
+            looks,
+            brain: None,
+            respawn_at: None,
+        }
+    }
+}
+
 struct Game<R, W: Write> {
     init_pos: usize,
     rows: usize,
     cols: usize,
-    score_to_speed: usize,
-    score: usize,
+    curve: Curve,
     food: usize,
-    spaces: usize,
-    snake: VecDeque<usize>,
+    // The empty cells of `map`, where new food can go.
+    free: FreeCells,
+    // Food eaten this game, or on this level of a campaign; it sets the pace.
+    eaten: usize,
+    // Bonus food on the map and the tick it goes off.
+    bonuses: Vec<(usize, u64)>,
+    // Whether fast or slow food has the snake going faster or slower, and
+    // until which tick.
+    boost: Option<(Boost, u64)>,
+    // The players' snakes, then the rivals'.
+    snakes: Vec<Snake>,
+    input: Decoder,
+    paused: bool,
+    terrain: Map,
     map: Vec<object::Object>,
     stdin: R,
     stdout: W,
-    rng: rand::rngs::ThreadRng,
+    // What the terminal shows, so a frame only writes what changed.
+    screen: Screen,
+    // The seed the current game started from; `--seed` replays it.
+    seed: u64,
+    rng: SplitMix64,
+    // Whether the last move ran into something.
+    over: bool,
+    // Moves made since the program started; replays are stamped with it.
+    ticks: u64,
+    recorder: Option<Replay>,
+    playback: Option<Playback>,
+    // The map's name on the high-score table.
+    board_name: String,
+    // Whether the current game had its chance at the high-score table, the
+    // name being typed in for it, and where it ended up.
+    scored: bool,
+    naming: Option<NameInput>,
+    placed: Option<String>,
+    // The campaign being played, the level it is on, the score the level
+    // started with and how long it has been played.
+    campaign: Option<Campaign>,
+    level: usize,
+    level_score: usize,
+    played: Duration,
+    // Whether the level's goal is met.
+    cleared: bool,
+    // Whether the snake filled the whole board.
+    won: bool,
+    // How many play, the rounds each has won and whether a round is over
+    // with the match still on, and who took it; nobody on a draw.
+    players: usize,
+    wins: [usize; MAX_PLAYERS],
+    round_over: bool,
+    round_winner: Option<usize>,
+    rivals: Rivals,
+    // Steers the first snake for `--autoplay`.
+    autopilot: Option<Autopilot>,
+    // The kind and size of a `--generate`d map, built again from every new
+    // seed.
+    generated: Option<(Kind, usize, usize)>,
 }
 
 impl<R: Read, W: Write> Game<R, W> {
-    fn new(stdin: R, stdout: W, map: &mut Vec<u8>, cols: usize) -> Self {
-        let init_pos = map.len() / 2;
-        map[init_pos] = object::SNAKE_HEAD;
+    fn new(stdin: R, stdout: W, terrain: Map, seed: u64, curve: Curve) -> Self {
         let mut game = Game {
-            init_pos: init_pos,
-            rows: map.len() / cols,
-            cols: cols,
-            score_to_speed: map.len() / 3 / SPEEDS.len(),
-            score: 0,
+            init_pos: terrain.start(),
+            rows: terrain.rows(),
+            cols: terrain.cols,
+            curve,
             food: 0,
-            spaces: 0,
-            snake: VecDeque::new(),
-            map: map.to_owned(),
-            stdin: stdin,
-            stdout: stdout,
-            rng: rand::thread_rng(),
+            free: FreeCells::new(),
+            eaten: 0,
+            bonuses: Vec::new(),
+            boost: None,
+            snakes: Vec::new(),
+            input: Decoder::new(),
+            paused: false,
+            map: terrain.cells.clone(),
+            terrain,
+            stdin,
+            stdout,
+            screen: Screen::new(),
+            seed,
+            rng: SplitMix64::new(seed),
+            over: false,
+            ticks: 0,
+            recorder: None,
+            playback: None,
+            board_name: String::new(),
+            scored: false,
+            naming: None,
+            placed: None,
+            campaign: None,
+            level: 0,
+            level_score: 0,
+            played: Duration::from_secs(0),
+            cleared: false,
+            won: false,
+            players: 1,
+            wins: [0; MAX_PLAYERS],
+            round_over: false,
+            round_winner: None,
+            rivals: Rivals::none(),
+            autopilot: None,
+            generated: None,
         };
         game.reset();
+        // Lets the game pause itself when the terminal loses focus.
+        write!(game.stdout, "{}", input::FOCUS_ON).unwrap();
 
         game
     }
 
     fn start(&mut self) {
-        self.feed();
-        self.draw();
-        let mut speed = self.try_speed_up(true);
         let mut keys_buf = [0u8; 23]; // Whatever
-        let mut prev_direction = Key::rand_direction(&mut self.rng);
-        let mut start = Instant::now();
-
-        loop {
-            let keys_num = self.stdin.read(&mut keys_buf).unwrap();
-            let mut key = prev_direction;
-            if keys_num > 0 {
-                key = Key::from(keys_buf[keys_num - 1], prev_direction);
-            }
-
-            let game_over = match key {
-                Key::Up => self.up(),
-                Key::Right => self.right(),
-                Key::Down => self.down(),
-                Key::Left => self.left(),
-                Key::Restart => {
-                    self.reset();
-                    self.start();
-                    return;
-                }
-                Key::Quit => return,
+
+        'game: loop {
+            self.feed();
+            self.redraw();
+            let mut speed = self.curve.start;
+            let heading = match self.terrain.direction {
+                Some(direction) => direction,
+                None => Key::rand_direction(&mut self.rng),
             };
-            prev_direction = key;
+            self.head_off(heading);
+            let mut start = Instant::now();
+
+            loop {
+                let key = if self.playback.is_some() {
+                    let inputs = self.read_inputs(&mut keys_buf);
+                    if !self.control_playback(&inputs) {
+                        return;
+                    }
+                    match self.replay_key() {
+                        Some(key_byte) => Key::from(key_byte, self.snakes[0].heading),
+                        None => {
+                            sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    }
+                } else if self.naming.is_some() {
+                    for input in self.read_inputs(&mut keys_buf) {
+                        self.enter_name(input);
+                    }
+                    sleep(POLL_INTERVAL);
+                    continue;
+                } else if self.paused {
+                    for input in self.read_inputs(&mut keys_buf) {
+                        match input {
+                            Input::Char(b'p') | Input::Char(b' ') => self.paused = false,
+                            Input::Char(b'q') | Input::Esc => {
+                                self.record(Key::Quit);
+                                return;
+                            }
+                            _ => {}
+                        }
+                    }
+                    if !self.paused {
+                        self.redraw();
+                    }
+                    // The tick timer stands still while paused.
+                    start = Instant::now();
+                    sleep(POLL_INTERVAL);
+                    continue;
+                } else if self.cleared && !self.over {
+                    for input in self.read_inputs(&mut keys_buf) {
+                        match input {
+                            Input::Char(b'\r') | Input::Char(b'\n') | Input::Char(b' ') => {
+                                self.load_level(self.level + 1, self.snakes[0].score);
+                                self.reset();
+                                continue 'game;
+                            }
+                            Input::Char(b'q') | Input::Esc => return,
+                            _ => {}
+                        }
+                    }
+                    sleep(POLL_INTERVAL);
+                    continue;
+                } else if self.round_over {
+                    for input in self.read_inputs(&mut keys_buf) {
+                        match input {
+                            Input::Char(b'\r') | Input::Char(b'\n') | Input::Char(b' ') => {
+                                self.reseed();
+                                self.reset();
+                                continue 'game;
+                            }
+                            Input::Char(b'q') | Input::Esc => return,
+                            _ => {}
+                        }
+                    }
+                    sleep(POLL_INTERVAL);
+                    continue;
+                } else {
+                    let inputs = self.read_inputs(&mut keys_buf);
+                    let key = self.read_key(&inputs);
+                    if self.paused {
+                        self.redraw();
+                        continue;
+                    }
+                    // The autopilot takes the turns; the keys still restart
+                    // and quit.
+                    let key = match self.autopilot {
+                        Some(ref pilot) if key.opposite().is_some() => {
+                            pilot.steer(&self.terrain, &self.map, &self.snakes[0])
+                        }
+                        _ => key,
+                    };
+                    if key != self.snakes[0].heading {
+                        self.record(key);
+                    }
+                    key
+                };
+                self.ticks += 1;
+
+                self.over = match key {
+                    Key::Up | Key::Right | Key::Down | Key::Left => {
+                        let crashed = self.move_to(key);
+                        self.tend_rivals(&crashed);
+                        if self.players > 1 {
+                            self.end_round(&crashed[..self.players])
+                        } else {
+                            crashed[0]
+                        }
+                    }
+                    Key::Restart => {
+                        self.reseed();
+                        // A campaign starts over from its first level, a match
+                        // from its first round.
+                        if self.campaign.is_some() {
+                            self.load_level(0, 0);
+                        }
+                        self.wins = [0; MAX_PLAYERS];
+                        self.reset();
+                        continue 'game;
+                    }
+                    Key::Quit => return,
+                };
+                if self.snakes.len() == 1 && !self.over && self.board_full() {
+                    self.won = true;
+                    self.over = true;
+                }
+                if !self.over && self.goal_met() {
+                    self.cleared = true;
+                    // Clearing the last level ends the campaign.
+                    self.over = self.level + 1 == self.campaign.as_ref().unwrap().levels.len();
+                }
+                if self.over {
+                    self.offer_high_score();
+                } else {
+                    self.spoil();
+                }
+
+                self.feed();
+                self.redraw();
+                // Continue to sleep to avoid busy loop once the game is over.
+
+                if self.playback.as_ref().is_some_and(|p| p.is_fast()) {
+                    speed /= FAST_FORWARD;
+                }
+                let elapsed = start.elapsed();
+                if speed > elapsed {
+                    sleep(speed - elapsed);
+                }
+                if !self.over {
+                    self.played += start.elapsed();
+                }
+                start = Instant::now();
+                // A finished game falls back to the slowest tick.
+                let cells = self.map.len();
+                speed = if self.over {
+                    self.curve.start
+                } else {
+                    match self.boost {
+                        Some((Boost::Fast, _)) => self.curve.tick(self.eaten, cells) * 2 / 3,
+                        Some((Boost::Slow, _)) => self.curve.tick(self.eaten, cells) * 3 / 2,
+                        None => self.curve.tick(self.eaten, cells),
+                    }
+                };
+            }
+        }
+    }
 
+    // Plays a game out with the autopilot as fast as it goes, nothing drawn,
+    // for `--benchmark`.
+    fn simulate(&mut self) -> (usize, u64, Ending) {
+        let pilot = self.autopilot.take().unwrap();
+        self.feed();
+        let heading = match self.terrain.direction {
+            Some(direction) => direction,
+            None => Key::rand_direction(&mut self.rng),
+        };
+        self.head_off(heading);
+        let mut last_meal = (0, self.snakes[0].score);
+        let ending = loop {
+            self.ticks += 1;
+            let key = pilot.steer(&self.terrain, &self.map, &self.snakes[0]);
+            if self.move_to(key)[0] {
+                break Ending::Crashed;
+            }
+            if self.board_full() {
+                break Ending::Filled;
+            }
+            self.spoil();
             self.feed();
-            self.draw();
-            if game_over {
-                self.draw_menu();
-                // Continue to sleep to avoid busy loop.
+            if self.snakes[0].score != last_meal.1 {
+                last_meal = (self.ticks, self.snakes[0].score);
+            } else if self.ticks - last_meal.0 > (self.map.len() * STALL_LAPS) as u64 {
+                break Ending::Stuck;
             }
+        };
+        self.autopilot = Some(pilot);
+        (self.snakes[0].score, self.ticks, ending)
+    }
 
-            let elapsed = start.elapsed();
-            if speed > elapsed {
-                sleep(speed - elapsed);
+    // The key for this tick: a restart or quit right away, else the next
+    // queued turn, else the current heading.
+    // Pausing, by key or by the terminal losing focus, holds the queued turns
+    // for when the game resumes.
+    // With two players the arrows steer the second snake.
+    fn read_key(&mut self, inputs: &[Input]) -> Key {
+        for input in inputs.iter() {
+            match *input {
+                Input::Char(b'p') | Input::Char(b' ') | Input::FocusOut if !self.over => {
+                    self.paused = true;
+                    continue;
+                }
+                _ => {}
             }
-            start = Instant::now();
-            speed = self.try_speed_up(game_over);
+            match Key::from_input(*input) {
+                Some(key @ Key::Restart) | Some(key @ Key::Quit) => return key,
+                Some(turn) => {
+                    let arrow =
+                        matches!(*input, Input::Up | Input::Down | Input::Left | Input::Right);
+                    let player = if self.players > 1 && arrow { 1 } else { 0 };
+                    self.queue_turn(player, turn);
+                }
+                None => {}
+            }
+        }
+        let snake = &mut self.snakes[0];
+        if self.paused {
+            return snake.heading;
+        }
+        snake.turns.pop_front().unwrap_or(snake.heading)
+    }
+
+    fn record(&mut self, key: Key) {
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.push(self.ticks, key.code() as char);
+        }
+    }
+
+    // Queues a turn for a player's snake unless it changes nothing after the
+    // turns already queued, or reverses into the snake's own neck.
+    fn queue_turn(&mut self, player: usize, turn: Key) {
+        let snake = &mut self.snakes[player];
+        let last = snake.turns.back().cloned().unwrap_or(snake.heading);
+        if snake.turns.len() < MAX_TURNS && turn != last && Some(turn) != last.opposite() {
+            snake.turns.push_back(turn);
+        }
+    }
+
+    // The key pressed on this tick of a replay, 0 for none. Returns `None` to
+    // hold the game while paused or once the recorded session has quit.
+    fn replay_key(&mut self) -> Option<u8> {
+        let playback = self.playback.as_mut().unwrap();
+        if playback.is_finished() || !playback.advance() {
+            return None;
+        }
+        match playback.pop_due(self.ticks) {
+            Some('q') => {
+                self.redraw();
+                None
+            }
+            Some(key) => Some(key as u8),
+            None => Some(0),
+        }
+    }
+
+    // Keys only steer the playback of a replay. Returns false to quit.
+    fn control_playback(&mut self, inputs: &[Input]) -> bool {
+        if inputs.is_empty() {
+            return true;
+        }
+        {
+            let playback = self.playback.as_mut().unwrap();
+            for input in inputs.iter() {
+                match *input {
+                    Input::Char(b' ') => playback.toggle_pause(),
+                    Input::Char(b'.') => playback.step(),
+                    Input::Char(b'f') => playback.toggle_fast(),
+                    Input::Char(b'q') | Input::Esc => return false,
+                    _ => {}
+                }
+            }
+        }
+        self.redraw();
+        true
+    }
+
+    // Lets the player put a finished game on the high-score table; a replay,
+    // a match between two players, a game against rivals after the same
+    // food, a game the autopilot played, or a test run of the map editor
+    // with its unnamed map, earns no place.
+    fn offer_high_score(&mut self) {
+        if self.scored
+            || self.playback.is_some()
+            || self.players > 1
+            || self.rivals.count > 0
+            || self.autopilot.is_some()
+            || self.board_name.is_empty()
+        {
+            return;
+        }
+        self.scored = true;
+        let score = self.snakes[0].score as u64;
+        if Scores::load().is_ok_and(|s| s.qualifies("snake", &self.board_name, score)) {
+            self.naming = Some(NameInput::new());
+        }
+    }
+
+    fn read_inputs(&mut self, keys_buf: &mut [u8]) -> Vec<Input> {
+        let keys_num = self.stdin.read(keys_buf).unwrap();
+        let mut inputs = self.input.feed(&keys_buf[..keys_num]);
+        // Ctrl-l draws the whole screen again, whatever the game is at.
+        if inputs.contains(&Input::Char(CTRL_L)) {
+            inputs.retain(|input| *input != Input::Char(CTRL_L));
+            self.screen.refresh();
+            self.redraw();
+        }
+        inputs
+    }
+
+    fn enter_name(&mut self, input: Input) {
+        let key_byte = match input {
+            Input::Char(key_byte) => key_byte,
+            Input::Esc => 0x1b,
+            _ => return,
+        };
+        match key_byte {
+            b'\r' | b'\n' => {
+                let name = match self.naming.take() {
+                    Some(name) => name,
+                    None => return,
+                };
+                self.placed = Some(self.place(name.as_str()));
+            }
+            0x1b => self.naming = None,
+            0x7f | 0x08 => {
+                if let Some(ref mut name) = self.naming {
+                    name.pop();
+                }
+            }
+            b' '..=b'~' => {
+                if let Some(ref mut name) = self.naming {
+                    name.push(key_byte as char);
+                }
+            }
+            _ => return,
+        }
+        self.redraw();
+    }
+
+    // Puts the score on the table and describes where it landed.
+    fn place(&self, name: &str) -> String {
+        let mut table = match Scores::load() {
+            Ok(table) => table,
+            Err(_) => return "  not saved".to_string(),
+        };
+        let score = self.snakes[0].score as u64;
+        match table.insert("snake", &self.board_name, score, name) {
+            Some(rank) if table.save().is_ok() => format!("  rank: #{}", rank),
+            Some(_) => "  not saved".to_string(),
+            None => "  not placed".to_string(),
+        }
+    }
+
+    fn redraw(&mut self) {
+        if !(self.paused || self.over || self.cleared || self.round_over) {
+            self.screen.uncover(&mut self.stdout);
+        }
+        self.draw();
+        if self.paused {
+            self.draw_pause();
+        } else if self.over {
+            self.draw_menu();
+        } else if self.cleared {
+            self.draw_level_done();
+        } else if self.round_over {
+            self.draw_round_over();
+        }
+    }
+
+    fn goal(&self) -> Option<Goal> {
+        self.campaign
+            .as_ref()
+            .map(|campaign| campaign.levels[self.level].goal)
+    }
+
+    fn goal_met(&self) -> bool {
+        match self.goal() {
+            Some(Goal::Eat(n)) => self.eaten >= n,
+            Some(Goal::Survive(secs)) => self.played.as_secs() >= secs,
+            None => false,
+        }
+    }
+
+    // The level and how far along its goal is, for the header.
+    fn progress(&self) -> String {
+        let level = self.level + 1;
+        match self.goal() {
+            Some(Goal::Eat(n)) => {
+                let eaten = self.eaten.min(n);
+                format!("LEVEL {} {}/{} ", level, eaten, n)
+            }
+            Some(Goal::Survive(secs)) => {
+                let played = self.played.as_secs().min(secs);
+                format!("LEVEL {} {}/{}s ", level, played, secs)
+            }
+            None => String::new(),
+        }
+    }
+
+    // Switches to a level of the campaign, which starts with `score`.
+    fn load_level(&mut self, level: usize, score: usize) {
+        let terrain = self.campaign.as_ref().unwrap().levels[level].map.clone();
+        self.load_terrain(terrain);
+        self.level = level;
+        self.level_score = score;
+    }
+
+    fn load_terrain(&mut self, terrain: Map) {
+        self.init_pos = terrain.start();
+        self.rows = terrain.rows();
+        self.cols = terrain.cols;
+        self.map = terrain.cells.clone();
+        if self.autopilot.is_some() {
+            self.autopilot = Some(Autopilot::new(&terrain));
+        }
+        self.terrain = terrain;
+        // The new map may have other edges, drawn only with the frame.
+        self.screen.refresh();
+    }
+
+    // Every game gets its own seed, so the one on the menu reproduces it,
+    // and a generated map is built again from it.
+    fn reseed(&mut self) {
+        self.seed = self.rng.gen::<u32>() as u64;
+        if let Some((kind, cols, rows)) = self.generated {
+            let content = generate::generate(kind, cols, rows, self.terrain.edges, self.seed);
+            // Generated maps always parse.
+            self.load_terrain(map::parse(&content).unwrap());
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        match self.playback {
+            Some(ref playback) if playback.is_finished() => "END ",
+            Some(ref playback) if playback.is_paused() => "PAUSED ",
+            Some(ref playback) if playback.is_fast() => "FAST ",
+            Some(_) => "REPLAY ",
+            None if self.autopilot.is_some() => "AUTO ",
+            None => "",
         }
     }
 
     fn reset(&mut self) {
-        self.snake.clear();
-        self.snake.push_back(self.init_pos);
-        self.score = 0;
+        self.rng = SplitMix64::new(self.seed);
+        self.over = false;
+        self.paused = false;
+        self.scored = false;
+        self.naming = None;
+        self.placed = None;
+        self.round_over = false;
+        self.round_winner = None;
+        self.eaten = 0;
+        self.bonuses.clear();
+        self.boost = None;
+        self.played = Duration::from_secs(0);
+        self.cleared = false;
+        self.won = false;
         self.food = 0;
-        self.spaces = 0;
-        for i in 0..(self.rows * self.cols) {
-            let obj = self.map[i];
-            if obj != object::SPACE && obj != object::BARRIER {
-                self.map[i] = object::SPACE;
-            }
-            if obj == object::SPACE && self.init_pos != i {
-                self.spaces += 1;
+        self.map.copy_from_slice(&self.terrain.cells);
+        self.free.clear(self.map.len());
+        // Cells walled off from the start never get food, nor have to be
+        // filled for the board to be full.
+        let reachable = self.terrain.reachable(self.init_pos);
+        for (pos, obj) in self.map.iter().enumerate() {
+            if *obj == object::SPACE && reachable[pos] {
+                self.free.insert(pos);
             }
         }
-        self.map[self.init_pos] = object::SNAKE_HEAD;
+        self.snakes.clear();
+        let mut first = Snake::new((object::SNAKE_HEAD, object::SNAKE_BODY));
+        // A campaign carries the score from one level to the next.
+        first.score = self.level_score;
+        self.snakes.push(first);
+        self.spawn(0, self.init_pos);
+        if self.players > 1 {
+            self.snakes
+                .push(Snake::new((object::SNAKE2_HEAD, object::SNAKE2_BODY)));
+            let pos = self.second_start();
+            self.spawn(1, pos);
+        }
+        for i in 0..self.rivals.count {
+            let strategy = self.rivals.strategy(i);
+            let mut rival = Snake::new(strategy.looks());
+            rival.brain = Some(strategy);
+            self.snakes.push(rival);
+            self.spawn_rival(self.snakes.len() - 1);
+        }
     }
 
-    fn snake_head_pos(&self) -> (usize, usize) {
-        let pos = self.snake[0];
-        (pos / self.cols, pos % self.cols)
+    // Puts snake `i` down on `pos`, one cell long.
+    fn spawn(&mut self, i: usize, pos: usize) {
+        self.snakes[i].body.clear();
+        self.snakes[i].body.push_back(pos);
+        self.snakes[i].respawn_at = None;
+        self.put(pos, self.snakes[i].looks.0);
     }
 
-    fn pos(&mut self, x: usize, y: usize) -> usize {
-        let x = if x > self.rows {
-            // overflow
-            self.rows - 1
-        } else if x == self.rows {
-            0
-        } else {
-            x
+    // Puts rival `i` down on a random empty cell, out in the open if one
+    // turns up, heading any way it can go, out of the players' way. With no
+    // such cell it tries again on the next tick.
+    fn spawn_rival(&mut self, i: usize) {
+        let mut spot = None;
+        for _ in 0..RIVAL_SPOT_TRIES {
+            let pos = match self.free.sample(&mut self.rng) {
+                Some(pos) => pos,
+                None => break,
+            };
+            if self.in_players_way(pos) {
+                continue;
+            }
+            spot = Some(pos);
+            if DIRECTIONS
+                .iter()
+                .all(|dir| self.passable(pos, *dir).is_some())
+            {
+                break;
+            }
+        }
+        let pos = match spot {
+            Some(pos) => pos,
+            None => {
+                self.snakes[i].respawn_at = Some(self.ticks + 1);
+                return;
+            }
         };
-        let y = if y > self.cols {
-            // overflow
-            self.cols - 1
-        } else if y == self.cols {
-            0
-        } else {
-            y
+        self.spawn(i, pos);
+        let open: Vec<Key> = DIRECTIONS
+            .iter()
+            .cloned()
+            .filter(|dir| self.passable(pos, *dir).is_some())
+            .collect();
+        self.snakes[i].heading = match open.len() {
+            0 => Key::rand_direction(&mut self.rng),
+            n => open[self.rng.gen_range(0, n)],
         };
+    }
 
-        x * self.cols + y
+    // Whether a snake put down on `pos` could be run into by a player before
+    // the player can turn away: close to its head, or on the cells ahead of
+    // it whichever way it goes next.
+    fn in_players_way(&self, pos: usize) -> bool {
+        self.snakes
+            .iter()
+            .take(self.players)
+            .filter_map(|snake| snake.body.front())
+            .any(|&head| {
+                if self.terrain.distance(head, pos) <= RIVAL_CLEARANCE {
+                    return true;
+                }
+                DIRECTIONS.iter().any(|dir| {
+                    let mut at = head;
+                    for _ in 0..RIVAL_RUNWAY {
+                        match self.terrain.target(at, *dir) {
+                            Some(next) if next == pos => return true,
+                            Some(next) => at = next,
+                            None => break,
+                        }
+                    }
+                    false
+                })
+            })
     }
 
-    fn up(&mut self) -> bool {
-        let (x, y) = self.snake_head_pos();
-        self.move_to(x - 1, y)
+    // Clears the rivals that ran into something off the map, and brings back
+    // the dead ones whose time has come.
+    fn tend_rivals(&mut self, crashed: &[bool]) {
+        for (i, crashed) in crashed.iter().enumerate().skip(self.players) {
+            if *crashed {
+                while let Some(pos) = self.snakes[i].body.pop_back() {
+                    self.put(pos, self.terrain.cells[pos]);
+                }
+                self.snakes[i].respawn_at = match self.rivals.respawn {
+                    Respawn::Never => None,
+                    Respawn::After(ticks) => Some(self.ticks + ticks),
+                };
+            } else if self.snakes[i].respawn_at.is_some_and(|at| at <= self.ticks) {
+                self.spawn_rival(i);
+            }
+        }
     }
 
-    fn down(&mut self) -> bool {
-        let (x, y) = self.snake_head_pos();
-        self.move_to(x + 1, y)
+    // Where the second player starts: across the middle of the map from the
+    // first one, or across the row when that is too close, on the nearest
+    // cell the first one could get to.
+    fn second_start(&self) -> usize {
+        let (rows, cols) = (self.rows, self.cols);
+        let (row, col) = (self.init_pos / cols, self.init_pos % cols);
+        let (mut to_row, mut to_col) = (rows - 1 - row, cols - 1 - col);
+        if to_row.abs_diff(row) + to_col.abs_diff(col) < cols / 2 {
+            to_row = row;
+            to_col = (col + cols / 2) % cols;
+        }
+        let reachable = self.terrain.reachable(self.init_pos);
+        (0..self.map.len())
+            .filter(|pos| {
+                reachable[*pos] && *pos != self.init_pos && self.map[*pos] == object::SPACE
+            })
+            .min_by_key(|pos| (pos / cols).abs_diff(to_row) + (pos % cols).abs_diff(to_col))
+            .expect("a valid map has room past the start")
     }
 
-    fn left(&mut self) -> bool {
-        let (x, y) = self.snake_head_pos();
-        self.move_to(x, y - 1)
+    // Sets off the first snake towards `heading` and the second one the
+    // other way, or any way it is not blocked.
+    fn head_off(&mut self, heading: Key) {
+        self.snakes[0].heading = heading;
+        if self.players < 2 {
+            return;
+        }
+        let pos = self.snakes[1].body[0];
+        let away = heading.opposite().unwrap();
+        self.snakes[1].heading = [away, Key::Up, Key::Right, Key::Down, Key::Left]
+            .iter()
+            .cloned()
+            .find(|dir| self.passable(pos, *dir).is_some())
+            .unwrap_or(away);
+    }
+
+    // Moves every live snake one cell, the first one towards `dir`, the
+    // rivals where they steer and the others on their own heading; returns
+    // which of them ran into something.
+    // Snakes run into each other as they stand before the move, heads and
+    // all; two heads meeting on a cell take the shorter snake down, or both
+    // when they are as long.
+    fn move_to(&mut self, dir: Key) -> Vec<bool> {
+        let mut dirs = Vec::with_capacity(self.snakes.len());
+        for i in 0..self.snakes.len() {
+            let snake = &mut self.snakes[i];
+            dirs.push(match snake.brain {
+                _ if i == 0 => dir,
+                _ if snake.body.is_empty() => snake.heading,
+                Some(strategy) => strategy.steer(
+                    self.rivals.skill,
+                    (&self.terrain, &self.map),
+                    snake,
+                    &mut self.rng,
+                ),
+                None => snake.turns.pop_front().unwrap_or(snake.heading),
+            });
+        }
+        let targets: Vec<Option<usize>> = (0..self.snakes.len())
+            .map(|i| match self.snakes[i].body.front() {
+                Some(head) => self.passable(*head, dirs[i]),
+                None => None,
+            })
+            .collect();
+        let mut crashed: Vec<bool> = (0..self.snakes.len())
+            .map(|i| targets[i].is_none() && !self.snakes[i].body.is_empty())
+            .collect();
+        for i in 0..self.snakes.len() {
+            for j in i + 1..self.snakes.len() {
+                let (a, b) = (&self.snakes[i], &self.snakes[j]);
+                if targets[i].is_some() && targets[i] == targets[j] {
+                    crashed[i] = crashed[i] || a.body.len() <= b.body.len();
+                    crashed[j] = crashed[j] || b.body.len() <= a.body.len();
+                }
+            }
+        }
+        for i in 0..self.snakes.len() {
+            self.snakes[i].heading = dirs[i];
+            match targets[i] {
+                Some(pos) if !crashed[i] => self.enter(i, pos),
+                _ => {}
+            }
+        }
+        crashed
     }
 
-    fn right(&mut self) -> bool {
-        let (x, y) = self.snake_head_pos();
-        self.move_to(x, y + 1)
+    fn passable(&self, pos: usize, dir: Key) -> Option<usize> {
+        passable(&self.terrain, &self.map, pos, dir)
     }
 
-    fn move_to(&mut self, x: usize, y: usize) -> bool {
-        let pos = self.pos(x, y);
+    // Moves snake `i` onto `pos`, eating whatever is there.
+    fn enter(&mut self, i: usize, pos: usize) {
         match self.map[pos] {
-            object::BARRIER | object::SNAKE_BODY => true,
-            object::FOOD => {
-                self.snake.push_front(pos);
-                self.map[pos] = object::SNAKE_HEAD;
-                self.map[self.snake[1]] = object::SNAKE_BODY;
-                self.food -= 1;
-                self.score += 1;
-                false
-            }
-            object::SPACE => {
-                self.snake.push_front(pos);
-                self.map[pos] = object::SNAKE_HEAD;
-                self.map[self.snake[1]] = object::SNAKE_BODY;
-                let tail_pos = self.snake.pop_back().unwrap();
-                self.map[tail_pos] = object::SPACE;
-                false
+            object::FIXED_FOOD => {
+                self.advance(i, pos, true);
+                self.snakes[i].score += 1;
+                if i < self.players {
+                    self.eaten += 1;
+                }
             }
-            _ => unreachable!(),
+            food @ (object::FOOD
+            | object::BONUS_FOOD
+            | object::SHRINK_FOOD
+            | object::FAST_FOOD
+            | object::SLOW_FOOD
+            | object::GOLDEN_FOOD) => self.eat(i, pos, food),
+            _ => self.advance(i, pos, false),
         }
     }
 
-    fn feed(&mut self) {
-        // TODO(damnever): maintain a space vector..
-        while MAX_FOOD > self.food && self.spaces > 0 {
-            let idx = self.rng.gen_range(0, self.map.len());
-            if self.map[idx] == object::SPACE {
-                self.map[idx] = object::FOOD;
-                self.food += 1;
-                self.spaces -= 1;
+    // Tallies a round after the snakes in `crashed` ran into something: the
+    // player left standing takes it, a round where both go down is a draw.
+    // Returns true once a player has won the match.
+    fn end_round(&mut self, crashed: &[bool]) -> bool {
+        if !crashed.iter().any(|c| *c) {
+            return false;
+        }
+        let standing: Vec<usize> = (0..self.players).filter(|i| !crashed[*i]).collect();
+        self.round_winner = match standing.as_slice() {
+            [winner] => Some(*winner),
+            _ => None,
+        };
+        if let Some(winner) = self.round_winner {
+            self.wins[winner] += 1;
+            if self.wins[winner] == ROUNDS_TO_WIN {
+                return true;
             }
         }
+        self.round_over = true;
+        false
+    }
+
+    fn advance(&mut self, i: usize, pos: usize, grow: bool) {
+        let (head, body) = self.snakes[i].looks;
+        self.snakes[i].body.push_front(pos);
+        self.put(pos, head);
+        self.put(self.snakes[i].body[1], body);
+        if !grow {
+            let tail_pos = self.snakes[i].body.pop_back().unwrap();
+            self.put(tail_pos, self.terrain.cells[tail_pos]);
+        }
     }
 
-    fn try_speed_up(&mut self, init: bool) -> Duration {
-        if init {
-            SPEEDS[0]
+    // Sets a cell of the map, keeping the free cells in step with it.
+    fn put(&mut self, pos: usize, obj: object::Object) {
+        self.map[pos] = obj;
+        if obj == object::SPACE {
+            self.free.insert(pos);
         } else {
-            let mut idx = self.score / self.score_to_speed;
-            if idx >= SPEEDS.len() {
-                idx = SPEEDS.len() - 1;
+            self.free.remove(pos);
+        }
+    }
+
+    // Whether the snake covers every cell it could ever move onto, with no
+    // food left to eat.
+    fn board_full(&self) -> bool {
+        self.free.is_empty()
+            && self.food == 0
+            && self
+                .terrain
+                .foods
+                .iter()
+                .all(|pos| self.map[*pos] != object::FIXED_FOOD)
+    }
+
+    fn eat(&mut self, i: usize, pos: usize, food: object::Object) {
+        self.advance(i, pos, food != object::SHRINK_FOOD);
+        self.bonuses.retain(|&(bonus, _)| bonus != pos);
+        self.food -= 1;
+        self.snakes[i].score += match food {
+            object::BONUS_FOOD => BONUS_POINTS,
+            object::GOLDEN_FOOD => GOLDEN_POINTS,
+            _ => 1,
+        };
+        match food {
+            object::SHRINK_FOOD => {
+                // The head always stays.
+                for _ in 0..SHRINK_CELLS.min(self.snakes[i].body.len() - 1) {
+                    let tail_pos = self.snakes[i].body.pop_back().unwrap();
+                    self.put(tail_pos, self.terrain.cells[tail_pos]);
+                }
             }
-            SPEEDS[idx]
+            // What rivals eat does not set the pace.
+            _ if i >= self.players => {}
+            object::FAST_FOOD => self.boost = Some((Boost::Fast, self.ticks + BOOST_TICKS)),
+            object::SLOW_FOOD => self.boost = Some((Boost::Slow, self.ticks + BOOST_TICKS)),
+            _ => {}
+        }
+        if i < self.players {
+            self.eaten += 1;
+        }
+    }
+
+    // Takes the bonus food that has gone off off the map, and ends a boost
+    // whose time is up.
+    fn spoil(&mut self) {
+        let ticks = self.ticks;
+        while let Some(i) = self.bonuses.iter().position(|&(_, until)| until <= ticks) {
+            let (pos, _) = self.bonuses.swap_remove(i);
+            self.put(pos, object::SPACE);
+            self.food -= 1;
+        }
+        if self.boost.is_some_and(|(_, until)| until <= ticks) {
+            self.boost = None;
+        }
+    }
+
+    // Picks the kind of the next food by the weights in `object::FOODS`.
+    fn food_kind(&mut self) -> object::Object {
+        let total: u32 = object::FOODS.iter().map(|&(_, weight, _)| weight).sum();
+        let mut roll = self.rng.gen_range(0, total);
+        for &(food, weight, _) in object::FOODS.iter() {
+            if roll < weight {
+                return food;
+            }
+            roll -= weight;
+        }
+        object::FOOD
+    }
+
+    fn feed(&mut self) {
+        for i in 0..self.terrain.foods.len() {
+            let pos = self.terrain.foods[i];
+            if self.map[pos] == object::SPACE {
+                self.put(pos, object::FIXED_FOOD);
+            }
+        }
+        while MAX_FOOD > self.food {
+            let pos = match self.free.sample(&mut self.rng) {
+                Some(pos) => pos,
+                None => break,
+            };
+            let food = self.food_kind();
+            if food == object::BONUS_FOOD {
+                self.bonuses.push((pos, self.ticks + BONUS_TICKS));
+            }
+            self.put(pos, food);
+            self.food += 1;
         }
     }
 
     fn draw(&mut self) {
-        write!(
-            self.stdout,
-            "{}{}{}",
-            clear::All,
-            style::Reset,
-            cursor::Goto(1, 1)
-        )
-        .unwrap();
+        let (score, tag) = if self.players > 1 {
+            let player = |i: usize| {
+                format!(
+                    "P{} SCORE: {}  WINS: {}",
+                    i + 1,
+                    self.snakes[i].score,
+                    self.wins[i]
+                )
+            };
+            (player(0), player(1))
+        } else {
+            let score = format!("SCORE: {}", self.snakes[0].score);
+            (score, format!("{}{}", self.progress(), self.tag()))
+        };
+        // The legend of the players and the food kinds, under the map.
+        let mut legend = String::from(" ");
+        if self.players > 1 {
+            for (i, snake) in self.snakes.iter().take(self.players).enumerate() {
+                let bg = object::COLORS[snake.looks.0 as usize];
+                let keys = PLAYER_KEYS[i];
+                legend.push_str(&format!(" {}  {} P{} {}", bg, style::Reset, i + 1, keys));
+            }
+            legend.push_str("  ");
+        }
+        if self.rivals.count > 0 {
+            let mut shown = Vec::new();
+            for i in 0..self.rivals.count {
+                let strategy = self.rivals.strategy(i);
+                if !shown.contains(&strategy) {
+                    shown.push(strategy);
+                    let bg = object::COLORS[strategy.looks().0 as usize];
+                    legend.push_str(&format!(" {}  {} {}", bg, style::Reset, strategy.name()));
+                }
+            }
+            legend.push_str("  ");
+        }
+        for &(food, _, name) in object::FOODS.iter() {
+            let bg = object::COLORS[food as usize];
+            legend.push_str(&format!(" {}  {} {}", bg, style::Reset, name));
+        }
 
-        let bg_border = color::Bg(color::Rgb(255, 255, 204));
-        let width = (self.cols + 2) * 2;
-        // Header
-        let vir_line = format!(
-            "{}{:spaces$}{}\n\r",
-            bg_border,
-            " ",
-            style::Reset,
-            spaces = width,
+        let (terrain, cells) = (&self.terrain, &self.map);
+        let glyph = |pos: usize| match cells[pos] {
+            object::PORTAL => terrain.portals[&pos].0,
+            object::ONE_WAY => terrain.one_way.arrow(),
+            _ => ' ',
+        };
+        self.screen.draw(
+            &mut self.stdout,
+            (&score, &tag),
+            (terrain, cells),
+            &glyph,
+            &legend,
         );
+    }
 
-        self.stdout.write(vir_line.as_bytes()).unwrap();
-        let score = self.score.to_string();
-        write!(
-            self.stdout,
-            "{}  SCORE: {}{:space$}{}\n\r",
-            style::Bold,
-            score,
-            " ",
-            style::Reset,
-            space = width - 9 - score.len()
-        )
-        .unwrap();
+    fn draw_pause(&mut self) {
+        let lines = [
+            "  PAUSED".to_string(),
+            "  resume: p".to_string(),
+            "  quit: q".to_string(),
+            String::new(),
+        ];
+        self.overlay(&lines);
+    }
 
-        // Body
-        let mut line: String = String::new();
-        line.push_str(vir_line.as_str());
-        self.stdout.write(line.as_bytes()).unwrap();
-        for row in 0..self.rows {
-            line.clear();
-            line.push_str(format!("{}  {}", bg_border, style::Reset).as_str());
-            for col in 0..self.cols {
-                let bg = object::COLORS[self.map[row * self.cols + col] as usize];
-                line.push_str(format!("{}{:2}{}", bg, " ", style::Reset).as_str());
+    fn draw_menu(&mut self) {
+        let title = match self.round_winner {
+            Some(winner) if self.players > 1 => format!("  P{} WINS!", winner + 1),
+            _ if self.won => "  YOU WIN!".to_string(),
+            _ if self.cleared => "  ALL CLEAR!".to_string(),
+            _ => "  GAME OVER!".to_string(),
+        };
+        let mut lines = vec![title];
+        if self.players > 1 {
+            lines.push(self.match_score());
+        }
+        if let Some(ref name) = self.naming {
+            lines.push("  HIGH SCORE!".to_string());
+            lines.push(format!(" >{}_", name.as_str()));
+            lines.push("  enter: save".to_string());
+            lines.push("  ESC: skip".to_string());
+        } else {
+            lines.push("  restart: r".to_string());
+            lines.push("  quit: q".to_string());
+            lines.push(format!("  seed: {}", self.seed));
+            if let Some(ref placed) = self.placed {
+                lines.push(placed.clone());
             }
-            line.push_str(format!("{}  {}\n\r", bg_border, style::Reset).as_str());
-            self.stdout.write(line.as_bytes()).unwrap();
         }
-        line.clear();
-        line.push_str(vir_line.as_str());
-        self.stdout.write(line.as_bytes()).unwrap();
-        self.stdout.flush().unwrap();
+        lines.push(String::new());
+        self.overlay(&lines);
     }
 
-    fn draw_menu(&mut self) {
+    fn draw_level_done(&mut self) {
+        let next = self.campaign.as_ref().unwrap().levels[self.level + 1].goal;
+        let lines = [
+            format!("  LEVEL {} DONE", self.level + 1),
+            format!("  next: {}", next),
+            "  go: enter".to_string(),
+            "  quit: q".to_string(),
+            String::new(),
+        ];
+        self.overlay(&lines);
+    }
+
+    fn draw_round_over(&mut self) {
+        let title = match self.round_winner {
+            Some(winner) => format!("  ROUND TO P{}", winner + 1),
+            None => "  DRAW".to_string(),
+        };
+        let lines = [
+            title,
+            self.match_score(),
+            "  next: enter".to_string(),
+            "  quit: q".to_string(),
+            String::new(),
+        ];
+        self.overlay(&lines);
+    }
+
+    // The rounds each player has won.
+    fn match_score(&self) -> String {
+        format!("  P1 {} - {} P2", self.wins[0], self.wins[1])
+    }
+
+    // Draws a box over the map, the first line in bold.
+    fn overlay(&mut self, lines: &[String]) {
         let start = (self.cols / 3) as u16;
-        let bg = color::Bg(color::Rgb(128, 128, 128));
-        write!(
-            self.stdout,
-            "{}{}{}  GAME OVER!   {}\n\r",
-            cursor::Goto(start, 4),
-            bg,
-            style::Bold,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}  restart: r   {}\n\r",
-            cursor::Goto(start, 5),
-            bg,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}  quit: q      {}\n\r",
-            cursor::Goto(start, 6),
-            bg,
-            style::Reset
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}{}               {}\n\r",
-            cursor::Goto(start, 7),
-            bg,
-            style::Reset
-        )
-        .unwrap();
-        self.stdout.flush().unwrap();
+        self.screen.overlay(&mut self.stdout, start, lines);
     }
 }
 
@@ -396,7 +1585,8 @@ impl<R, W: Write> Drop for Game<R, W> {
     fn drop(&mut self) {
         write!(
             self.stdout,
-            "{}{}{}",
+            "{}{}{}{}",
+            input::FOCUS_OFF,
             clear::All,
             style::Reset,
             cursor::Goto(1, 1)
@@ -405,37 +1595,303 @@ impl<R, W: Write> Drop for Game<R, W> {
     }
 }
 
-fn parse_map(content: String) -> (Vec<u8>, usize) {
-    let mut map = Vec::new();
-    let reader = BufReader::new(Cursor::new(content));
-    let mut cols = 0usize;
+// Clears the screen and draws the board: a header line with its two parts
+// at either end, then the cells in a frame, two columns a cell, dark
+// along the solid edges. `glyph` is what is written over a cell and
+// `cursor` the cell the map editor points at.
+fn draw_board<W: Write>(
+    stdout: &mut W,
+    (left, right): (&str, &str),
+    cells: &[object::Object],
+    cols: usize,
+    edges: Edges,
+    glyph: &dyn Fn(usize) -> char,
+    cursor: Option<usize>,
+) {
+    write!(
+        stdout,
+        "{}{}{}",
+        clear::All,
+        style::Reset,
+        cursor::Goto(1, 1)
+    )
+    .unwrap();
+
+    let bg_border = color::Bg(color::Rgb(255, 255, 204));
+    let bg_solid = color::Bg(color::Rgb(51, 51, 51));
+    let width = (cols + 2) * 2;
+    let border_line = |bg: color::Bg<color::Rgb>| {
+        format!("{}{:spaces$}{}\n\r", bg, " ", style::Reset, spaces = width)
+    };
+    // Header
+    let vir_line = border_line(bg_border);
 
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if cols != 0 && line.len() != cols {
-            panic!("column number msmatch with previous one");
+    stdout.write_all(vir_line.as_bytes()).unwrap();
+    stdout
+        .write_all(header_line(left, right, width).as_bytes())
+        .unwrap();
+
+    // Body
+    let (bg_rows, bg_cols) = (
+        if edges.wraps_rows() {
+            bg_border
         } else {
-            cols = line.len();
-        }
-        for c in line.chars() {
-            if c == SPACE_MARK {
-                map.push(object::SPACE);
-            } else if c == BARRIER_MARK {
-                map.push(object::BARRIER);
+            bg_solid
+        },
+        if edges.wraps_cols() {
+            bg_border
+        } else {
+            bg_solid
+        },
+    );
+    let mut line: String = String::new();
+    line.push_str(border_line(bg_cols).as_str());
+    stdout.write_all(line.as_bytes()).unwrap();
+    for row in 0..cells.len() / cols {
+        line.clear();
+        line.push_str(format!("{}  {}", bg_rows, style::Reset).as_str());
+        for col in 0..cols {
+            let pos = row * cols + col;
+            let bg = object::COLORS[cells[pos] as usize];
+            let cell = if cursor == Some(pos) {
+                format!("{}{}[]", style::Bold, color::Fg(color::Rgb(255, 128, 0)))
             } else {
-                panic!("unknown mark {}", c);
+                format!("{:2}", glyph(pos))
+            };
+            line.push_str(format!("{}{}{}", bg, cell, style::Reset).as_str());
+        }
+        line.push_str(format!("{}  {}\n\r", bg_rows, style::Reset).as_str());
+        stdout.write_all(line.as_bytes()).unwrap();
+    }
+    line.clear();
+    line.push_str(border_line(bg_cols).as_str());
+    stdout.write_all(line.as_bytes()).unwrap();
+    stdout.flush().unwrap();
+}
+
+// The header line of a board `width` columns wide, its two parts at either
+// end.
+fn header_line(left: &str, right: &str, width: usize) -> String {
+    format!(
+        "{}  {}{:space$}{}{}\n\r",
+        style::Bold,
+        left,
+        " ",
+        right,
+        style::Reset,
+        space = width.saturating_sub(2 + left.chars().count() + right.chars().count())
+    )
+}
+
+// The cell a head moving from `pos` towards `dir` lands on, if it can go
+// there: not onto barriers, snakes, a wall crossed the wrong way, a portal
+// right behind another one, or off a solid edge.
+fn passable(terrain: &Map, cells: &[object::Object], pos: usize, dir: Key) -> Option<usize> {
+    let pos = terrain.target(pos, dir)?;
+    match cells[pos] {
+        object::SPACE => Some(pos),
+        object::ONE_WAY if dir == terrain.one_way => Some(pos),
+        obj if object::is_food(obj) => Some(pos),
+        _ => None,
+    }
+}
+
+// How many cells can be reached from `start`, counting up to `limit`.
+fn room(terrain: &Map, cells: &[object::Object], start: usize, limit: usize) -> usize {
+    let mut seen = vec![false; cells.len()];
+    let mut queue = VecDeque::new();
+    seen[start] = true;
+    queue.push_back(start);
+    let mut count = 1;
+    while let Some(pos) = queue.pop_front() {
+        for dir in DIRECTIONS.iter() {
+            if let Some(next) = passable(terrain, cells, pos, *dir) {
+                if !seen[next] {
+                    seen[next] = true;
+                    count += 1;
+                    if count >= limit {
+                        return count;
+                    }
+                    queue.push_back(next);
+                }
             }
         }
     }
+    count
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!(
+        "{}{}{}{}",
+        style::Bold,
+        color::Fg(color::Red),
+        msg,
+        style::Reset
+    );
+    process::exit(1);
+}
+
+// Parses `WxH`.
+fn parse_size(s: &str) -> Option<(usize, usize)> {
+    let mut parts = s.splitn(2, ['x', 'X']);
+    let cols = parts.next()?.trim().parse::<usize>().ok()?;
+    let rows = parts.next()?.trim().parse::<usize>().ok()?;
+    let range = generate::MIN_SIZE..=generate::MAX_SIZE;
+    if !range.contains(&cols) || !range.contains(&rows) {
+        return None;
+    }
+    Some((cols, rows))
+}
+
+// Parses `KIND WxH`, as replays record a generated map.
+fn parse_generated(s: &str) -> Option<(Kind, usize, usize)> {
+    let mut words = s.split_whitespace();
+    let kind = Kind::parse(words.next()?)?;
+    let (cols, rows) = parse_size(words.next()?)?;
+    match words.next() {
+        Some(_) => None,
+        None => Some((kind, cols, rows)),
+    }
+}
+
+// A file's name without its extension, fit for the high-score table.
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(char::is_whitespace, "_"))
+        .unwrap_or_default()
+}
+
+// Runs the map editor on `path`, a new map if there is no such file yet.
+fn edit_map(path: String, curve: Curve) {
+    let draft = match fs::read(&path) {
+        Ok(content) => Draft::load(&map::decode(content).unwrap_or_else(|err| {
+            exit_with_error(&format!("Invalid map {}: {}", path, err));
+        })),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Draft::new(),
+        Err(err) => exit_with_error(&format!("Read file {}: {}", path, err)),
+    };
+    // Room for the frame and the header, as the game needs, and the lines
+    // under the map.
+    let (termwidth, termheight) = termion::terminal_size()
+        .unwrap_or_else(|err| exit_with_error(&format!("Terminal size: {}", err)));
+    let max_rows = (termheight as usize).saturating_sub(7);
+    let max_cols = ((termwidth as usize).saturating_sub(2) / 2).saturating_sub(2);
+    if draft.rows > max_rows || draft.cols > max_cols {
+        exit_with_error(&format!(
+            "The map is {}x{}, the terminal has room for {}x{}.",
+            draft.cols, draft.rows, max_cols, max_rows
+        ));
+    }
+
+    let stdout = io::stdout();
+    let stdout = stdout.lock().into_raw_mode().unwrap();
+    Editor::new(
+        async_stdin(),
+        stdout,
+        path,
+        draft,
+        curve,
+        (max_rows, max_cols),
+    )
+    .run();
+}
+
+// Plays `games` games on `map` with the autopilot, headless, the first from
+// `seed` and each one after from the next seed, and reports how they went.
+fn benchmark(map: Map, map_name: &str, seed: u64, games: u64) {
+    let pilot = Autopilot::new(&map);
+    println!(
+        "{} games on {} ({}x{}), {} autopilot, seeds from {}",
+        games,
+        map_name,
+        map.cols,
+        map.rows(),
+        pilot.name(),
+        seed
+    );
+    let (mut scores, mut ticks, mut filled, mut fill_ticks) = (0, 0, 0, 0);
+    for i in 0..games {
+        let seed = seed.wrapping_add(i);
+        let curve = Curve::difficulty(DEFAULT_DIFFICULTY).unwrap();
+        let mut game = Game::new(io::empty(), io::sink(), map.clone(), seed, curve);
+        game.autopilot = Some(Autopilot::new(&map));
+        let (score, played, ending) = game.simulate();
+        let verdict = match ending {
+            Ending::Filled => "filled the board",
+            Ending::Crashed => "crashed",
+            Ending::Stuck => "got stuck",
+        };
+        println!(
+            "  seed {}: score {}, {} ticks, {}",
+            seed, score, played, verdict
+        );
+        scores += score as u64;
+        ticks += played;
+        if ending == Ending::Filled {
+            filled += 1;
+            fill_ticks += played;
+        }
+    }
+    if games == 0 {
+        return;
+    }
+    println!(
+        "average score {:.1}, {:.0} ticks a game; filled the board in {} of {}",
+        scores as f64 / games as f64,
+        ticks as f64 / games as f64,
+        filled,
+        games
+    );
+    if filled > 0 {
+        println!(
+            "average ticks to fill the board: {:.0}",
+            fill_ticks as f64 / filled as f64
+        );
+    }
+}
 
-    (map, cols)
+// Validates a map file for `--check-map` and exits with the verdict.
+fn check_map(path: &str) -> ! {
+    let content = fs::read(path).unwrap_or_else(|err| {
+        exit_with_error(&format!("Read file {}: {}", path, err));
+    });
+    match map::decode(content).and_then(|content| map::parse(&content)) {
+        Ok(map) => {
+            println!("{}: ok ({}x{})", path, map.cols, map.rows());
+            process::exit(0);
+        }
+        Err(err) => exit_with_error(&format!("{}: {}", path, err)),
+    }
 }
 
 pub fn main() {
     let args: Vec<String> = env::args().collect();
     let mut map_content = DEFAULT_MAP.to_string();
-    if args.len() > 1 {
-        match args[1].as_str() {
+    let mut map_name = "default".to_string();
+    // Where the map came from, for errors.
+    let mut map_source = "default".to_string();
+    let mut campaign = None;
+    let mut edit = None;
+    let mut generate = None;
+    let mut size = None;
+    let mut export = None;
+    let mut edges = None;
+    let mut seed = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut replay_speed = Speed::Normal;
+    let mut curve = Curve::difficulty(DEFAULT_DIFFICULTY).unwrap();
+    let mut start_tick = None;
+    let mut players = 1;
+    let mut rivals = Rivals::none();
+    // Whether any of the rival options beyond their count was given.
+    let mut rival_options = false;
+    let mut autoplay = false;
+    let mut benchmark_games = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
             "--help" | "-h" => {
                 eprintln!("Usage: {} [OPTIONS]", &args[0]);
                 eprintln!("\nOPTIONS:");
@@ -443,38 +1899,479 @@ pub fn main() {
                 eprintln!(
                     "  --map/-m FILE  The map file path, '*' means wall, '.' means an empty space,"
                 );
-                eprintln!("                 the snake will start from the center of the map.");
+                eprintln!(
+                    "                 '@' the start (the center by default), 'o' food that grows"
+                );
+                eprintln!(
+                    "                 back, '#' a one-way wall, and a digit or capital letter"
+                );
+                eprintln!(
+                    "                 one end of a portal pair. '% direction D' sets the first"
+                );
+                eprintln!("                 heading, '% oneway D' the way through one-way walls.");
+                eprintln!("  --campaign [FILE]");
+                eprintln!(
+                    "                 Play the levels of a campaign file, or the bundled one, in"
+                );
+                eprintln!("                 order; each level has a goal that opens the next.");
+                eprintln!("  --edit [FILE]  Draw a map in the map editor and save it to FILE");
+                eprintln!("                 (default: {}).", DEFAULT_EDIT_PATH);
+                eprintln!(
+                    "  --edges E      What the map edges do: wrap, solid, wrap-rows (only the left"
+                );
+                eprintln!(
+                    "                 and right edges wrap) or wrap-cols (only the top and bottom"
+                );
+                eprintln!("                 ones), over what the map says ('% edges E', wrap by default).");
+                eprintln!("  --generate KIND");
+                eprintln!(
+                    "                 Play a random map: scatter, rooms or maze. The seed picks"
+                );
+                eprintln!("                 the map as well as the game.");
+                eprintln!(
+                    "  --size WxH     The size of a generated map (default: {}x{}).",
+                    DEFAULT_GENERATE_COLS, DEFAULT_GENERATE_ROWS
+                );
+                eprintln!("  --export FILE  Save the generated map to FILE instead of playing it.");
+                eprintln!("  --check-map FILE");
+                eprintln!(
+                    "                 Check a map file for mistakes, print what is wrong and exit."
+                );
+                eprintln!(
+                    "  --difficulty D How fast the snake gets: classic, easy, normal, hard or"
+                );
+                eprintln!("                 insane (default: {}).", DEFAULT_DIFFICULTY);
+                eprintln!("  --curve START,MIN,ACCEL");
+                eprintln!(
+                    "                 A custom speed curve: the first tick, the fastest tick and"
+                );
+                eprintln!("                 how much faster each food makes it, in milliseconds.");
+                eprintln!("  --speed MS     The first tick in milliseconds, on whatever curve.");
+                eprintln!("  --players N    1, or 2 for a match on one keyboard: WASD against the");
+                eprintln!(
+                    "                 arrows, first to {} rounds.",
+                    ROUNDS_TO_WIN
+                );
+                eprintln!(
+                    "  --rivals N     Add N snakes steered by the computer (up to {}).",
+                    rival::MAX_RIVALS
+                );
+                eprintln!("  --rival-strategy S[,S...]");
+                eprintln!(
+                    "                 How rivals steer: greedy, bfs or random, taken in turn"
+                );
+                eprintln!("                 (default: greedy).");
+                eprintln!("  --rival-skill S");
+                eprintln!(
+                    "                 How well rivals play: easy, normal or hard (default: normal)."
+                );
+                eprintln!("  --respawn R    When dead rivals come back: never, or after R ticks");
+                eprintln!("                 (default: {}).", Rivals::none().respawn);
+                eprintln!(
+                    "  --autoplay     Let the autopilot play: a Hamiltonian cycle with shortcuts"
+                );
+                eprintln!(
+                    "                 where the open cells make an even rectangle, A* elsewhere."
+                );
+                eprintln!(
+                    "  --benchmark N  Play N games with the autopilot, headless, and report how"
+                );
+                eprintln!("                 they went.");
+                eprintln!("  --seed N       Start from a fixed seed to replay a game.");
+                eprintln!("  --scores       Print the high-score table and exit.");
+                eprintln!("  --record FILE  Record every key of the session to FILE.");
+                eprintln!("  --replay FILE  Play back a recorded session.");
+                eprintln!("  --replay-speed S");
+                eprintln!(
+                    "                 Playback speed: normal, fast or step (default: normal)."
+                );
                 process::exit(1);
             }
             "--map" | "-m" => {
-                if args.len() < 3 {
-                    eprintln!(
-                        "{}{}Missing file path.{}",
-                        style::Bold,
-                        color::Fg(color::Red),
-                        style::Reset
-                    );
-                    process::exit(1);
-                }
-                let content = fs::read(&args[2]).unwrap_or_else(|err| {
-                    eprintln!(
-                        "{}{}Read file {}: {}{}",
-                        style::Bold,
-                        color::Fg(color::Red),
-                        &args[2],
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing file path.");
+                }
+                let content = fs::read(&args[i]).unwrap_or_else(|err| {
+                    exit_with_error(&format!("Read file {}: {}", &args[i], err));
+                });
+                map_content = map::decode(content).unwrap_or_else(|err| {
+                    exit_with_error(&format!("Invalid map {}: {}", &args[i], err));
+                });
+                map_source = args[i].clone();
+                // Scores are kept per map, under the map's file name.
+                map_name = file_name(&args[i]);
+            }
+            "--campaign" => {
+                // Without a file the bundled campaign is played.
+                campaign = Some(match args.get(i + 1) {
+                    Some(path) if !path.starts_with('-') => {
+                        i += 1;
+                        map_name = format!("campaign-{}", file_name(path));
+                        Campaign::read(path).unwrap_or_else(|err| {
+                            exit_with_error(&format!("Invalid campaign {}: {}", path, err));
+                        })
+                    }
+                    _ => {
+                        map_name = "campaign".to_string();
+                        Campaign::from_levels(&DEFAULT_CAMPAIGN).unwrap()
+                    }
+                });
+            }
+            "--edit" => {
+                edit = Some(match args.get(i + 1) {
+                    Some(path) if !path.starts_with('-') => {
+                        i += 1;
+                        path.clone()
+                    }
+                    _ => DEFAULT_EDIT_PATH.to_string(),
+                });
+            }
+            "--generate" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing map kind.");
+                }
+                generate = Some(Kind::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid map kind: {}", &args[i]));
+                }));
+            }
+            "--size" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing map size.");
+                }
+                size = Some(parse_size(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid map size: {}", &args[i]));
+                }));
+            }
+            "--export" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing file path.");
+                }
+                export = Some(args[i].clone());
+            }
+            "--edges" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing edges.");
+                }
+                edges = Some(Edges::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid edges: {}", &args[i]));
+                }));
+            }
+            "--check-map" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing file path.");
+                }
+                check_map(&args[i]);
+            }
+            "--difficulty" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing difficulty.");
+                }
+                curve = Curve::difficulty(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid difficulty: {}", &args[i]));
+                });
+            }
+            "--curve" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing speed curve.");
+                }
+                curve = Curve::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid speed curve: {}", &args[i]));
+                });
+            }
+            "--speed" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing speed.");
+                }
+                start_tick = match args[i].parse::<u64>() {
+                    Ok(ms) if ms > 0 => Some(ms),
+                    _ => exit_with_error(&format!("Invalid speed: {}", &args[i])),
+                };
+            }
+            "--players" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing number of players.");
+                }
+                players = match args[i].parse::<usize>() {
+                    Ok(n) if (1..=MAX_PLAYERS).contains(&n) => n,
+                    _ => exit_with_error(&format!("Invalid number of players: {}", &args[i])),
+                };
+            }
+            "--rivals" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing number of rivals.");
+                }
+                rivals.count = match args[i].parse::<usize>() {
+                    Ok(n) if n <= rival::MAX_RIVALS => n,
+                    _ => exit_with_error(&format!("Invalid number of rivals: {}", &args[i])),
+                };
+            }
+            "--rival-strategy" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing rival strategy.");
+                }
+                rivals.strategies = Strategy::parse_list(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid rival strategy: {}", &args[i]));
+                });
+                rival_options = true;
+            }
+            "--rival-skill" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing rival skill.");
+                }
+                rivals.skill = Skill::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid rival skill: {}", &args[i]));
+                });
+                rival_options = true;
+            }
+            "--respawn" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing respawn rule.");
+                }
+                rivals.respawn = Respawn::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid respawn rule: {}", &args[i]));
+                });
+                rival_options = true;
+            }
+            "--autoplay" => autoplay = true,
+            "--benchmark" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing number of games.");
+                }
+                benchmark_games = Some(args[i].parse::<u64>().unwrap_or_else(|_| {
+                    exit_with_error(&format!("Invalid number of games: {}", &args[i]));
+                }));
+            }
+            "--seed" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing seed.");
+                }
+                seed = Some(args[i].parse::<u64>().unwrap_or_else(|_| {
+                    exit_with_error(&format!("Invalid seed: {}", &args[i]));
+                }));
+            }
+            "--scores" => {
+                if let Err(err) = scores::print("snake") {
+                    exit_with_error(&format!(
+                        "Can not read the high-score table: {} (remove {} to start over)",
                         err,
-                        style::Reset
-                    );
-                    process::exit(1);
+                        scores::path().unwrap_or_default().display()
+                    ));
+                }
+                process::exit(0);
+            }
+            "--record" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing record file.");
+                }
+                record = Some(args[i].clone());
+            }
+            "--replay" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing replay file.");
+                }
+                replay = Some(Replay::read(&args[i], "snake").unwrap_or_else(|err| {
+                    exit_with_error(&format!("Can not read the replay {}: {}", &args[i], err));
+                }));
+            }
+            "--replay-speed" => {
+                i += 1;
+                if i >= args.len() {
+                    exit_with_error("Missing replay speed.");
+                }
+                replay_speed = Speed::parse(&args[i]).unwrap_or_else(|| {
+                    exit_with_error(&format!("Invalid replay speed: {}", &args[i]));
                 });
-                map_content = String::from_utf8(content).unwrap();
             }
-            _ => {}
+            arg => exit_with_error(&format!("Unknown option: {}", arg)),
+        }
+        i += 1;
+    }
+
+    if let Some(start) = start_tick {
+        curve = curve.with_start(start);
+    }
+
+    if generate.is_none() && (size.is_some() || export.is_some()) {
+        exit_with_error("--size and --export go with --generate.");
+    }
+    if generate.is_some()
+        && (campaign.is_some() || edit.is_some() || map_source != "default" || replay.is_some())
+    {
+        exit_with_error(
+            "--generate can not be combined with --campaign, --edit, --map or --replay.",
+        );
+    }
+
+    if (autoplay || benchmark_games.is_some())
+        && (players > 1
+            || rivals.count > 0
+            || campaign.is_some()
+            || edit.is_some()
+            || replay.is_some())
+    {
+        exit_with_error(
+            "--autoplay and --benchmark can not be combined with --players, --rivals, --campaign, --edit or --replay.",
+        );
+    }
+    if benchmark_games.is_some() && record.is_some() {
+        exit_with_error("--benchmark can not be combined with --record.");
+    }
+    if rivals.count == 0 && rival_options {
+        exit_with_error("--rival-strategy, --rival-skill and --respawn go with --rivals.");
+    }
+    if players > 1 && (campaign.is_some() || edit.is_some() || record.is_some() || replay.is_some())
+    {
+        exit_with_error(
+            "--players can not be combined with --campaign, --edit, --record or --replay.",
+        );
+    }
+
+    if let Some(path) = edit {
+        if campaign.is_some()
+            || map_source != "default"
+            || edges.is_some()
+            || record.is_some()
+            || replay.is_some()
+        {
+            exit_with_error(
+                "--edit can not be combined with --campaign, --map, --edges, --record or --replay.",
+            );
+        }
+        edit_map(path, curve);
+        return;
+    }
+
+    if campaign.is_some() && (map_source != "default" || record.is_some() || replay.is_some()) {
+        exit_with_error("--campaign can not be combined with --map, --record or --replay.");
+    }
+
+    let mut generated = None;
+    // A replay carries its own map, seed and pace.
+    if let Some(ref replay) = replay {
+        if record.is_some() {
+            exit_with_error("--replay can not be combined with --record.");
+        }
+        let rows: Vec<&str> = replay.options("map").collect();
+        if rows.is_empty() {
+            exit_with_error("The replay has no map.");
+        }
+        map_content = rows.join("\n");
+        map_source = "in the replay".to_string();
+        seed = Some(replay.seed);
+        if let Some(recorded) = replay.option("edges") {
+            edges = Some(
+                Edges::parse(recorded)
+                    .unwrap_or_else(|| exit_with_error("The replay has no valid edges.")),
+            );
+        }
+        if let Some(recorded) = replay.option("rivals") {
+            rivals = Rivals::parse(recorded)
+                .unwrap_or_else(|| exit_with_error("The replay has no valid rivals."));
+        }
+        if let Some(recorded) = replay.option("curve") {
+            curve = Curve::parse(recorded)
+                .unwrap_or_else(|| exit_with_error("The replay has no valid speed curve."));
+        }
+        if let Some(recorded) = replay.option("generate") {
+            generated = Some(
+                parse_generated(recorded)
+                    .unwrap_or_else(|| exit_with_error("The replay has no valid generated map.")),
+            );
         }
     }
+    let seed = seed.unwrap_or_else(rng::random_seed);
+    // A generated map comes from the game's seed, which brings it back.
+    if let Some(kind) = generate {
+        let (cols, rows) = size.unwrap_or((DEFAULT_GENERATE_COLS, DEFAULT_GENERATE_ROWS));
+        map_content = generate::generate(kind, cols, rows, edges.unwrap_or(Edges::Wrap), seed);
+        map_name = format!("{}-{}x{}", kind.name(), cols, rows);
+        generated = Some((kind, cols, rows));
+        if let Some(path) = export {
+            if let Err(err) = fs::write(&path, &map_content) {
+                exit_with_error(&format!("Write file {}: {}", path, err));
+            }
+            println!(
+                "{}: {} map, {}x{}, seed {}",
+                path,
+                kind.name(),
+                cols,
+                rows,
+                seed
+            );
+            return;
+        }
+    }
+    let recorder = record.as_ref().map(|_| {
+        let mut recorder = Replay::new("snake", seed);
+        for row in map_content.lines() {
+            recorder.add_option("map", row);
+        }
+        recorder.add_option("curve", &curve.to_string());
+        if let Some(edges) = edges {
+            recorder.add_option("edges", edges.name());
+        }
+        if rivals.count > 0 {
+            recorder.add_option("rivals", &rivals.to_string());
+        }
+        // Restarts build the map again from their seeds.
+        if let Some((kind, cols, rows)) = generated {
+            recorder.add_option("generate", &format!("{} {}x{}", kind.name(), cols, rows));
+        }
+        recorder
+    });
 
-    let (map, cols) = parse_map(map_content);
-    let rows = map.len() / cols;
+    // Bad maps are reported here, while the terminal is still cooked.
+    // `--edges` overrides what the maps say, which may box the snake in.
+    let map = match campaign {
+        Some(ref mut campaign) => {
+            for (i, level) in campaign.levels.iter_mut().enumerate() {
+                level.map.edges = edges.unwrap_or(level.map.edges);
+                level.map.validate().unwrap_or_else(|err| {
+                    exit_with_error(&format!("Invalid map for level {}: {}", i + 1, err));
+                });
+            }
+            campaign.levels[0].map.clone()
+        }
+        None => {
+            let mut map = map::parse(&map_content).unwrap_or_else(|err| {
+                exit_with_error(&format!("Invalid map {}: {}", map_source, err));
+            });
+            map.edges = edges.unwrap_or(map.edges);
+            map.validate().unwrap_or_else(|err| {
+                exit_with_error(&format!("Invalid map {}: {}", map_source, err));
+            });
+            map
+        }
+    };
+    if let Some(games) = benchmark_games {
+        benchmark(map, &map_name, seed, games);
+        return;
+    }
+    // The terminal has to fit the largest level.
+    let (rows, cols) = match campaign {
+        Some(ref campaign) => campaign.levels.iter().fold((0, 0), |(rows, cols), level| {
+            (rows.max(level.map.rows()), cols.max(level.map.cols))
+        }),
+        None => (map.rows(), map.cols),
+    };
 
     let stdout = io::stdout();
     let stdout = stdout.lock().into_raw_mode().unwrap();
@@ -482,20 +2379,65 @@ pub fn main() {
     let mut stderr = stderr.lock();
     let termsize = termion::terminal_size().ok();
     let (termwidth, termheight) = termsize.map(|(w, h)| (w - 2, h - 2)).unwrap();
-    if termheight < (rows + 4) as u16 || termwidth < (cols + 2) as u16 * 2 {
+    if termheight < (rows + 5) as u16 || termwidth < (cols + 2) as u16 * 2 {
         writeln!(
             stderr,
             "{}{}terminal size must satisfy with (width >= {}, height >= {}){}",
             style::Bold,
             color::Fg(color::Red),
-            rows + 4,
+            rows + 5,
             (cols + 2) * 2,
             style::Reset
         )
         .unwrap();
         process::exit(1);
     }
-    let mut map = map;
-    let mut g = Game::new(async_stdin(), stdout, &mut map, cols);
+    let autopilot = if autoplay {
+        Some(Autopilot::new(&map))
+    } else {
+        None
+    };
+    let mut g = Game::new(async_stdin(), stdout, map, seed, curve);
+    g.autopilot = autopilot;
+    g.recorder = recorder;
+    g.board_name = map_name;
+    g.generated = generated;
+    g.campaign = campaign;
+    g.playback = replay.map(|replay| Playback::new(&replay, replay_speed));
+    if players > 1 || rivals.count > 0 {
+        g.players = players;
+        g.rivals = rivals;
+        g.reset();
+    }
     g.start();
+
+    // Leave raw mode before reporting anything.
+    let recorder = g.recorder.take();
+    drop(g);
+    if let (Some(recorder), Some(path)) = (recorder, record) {
+        if let Err(err) = recorder.write(&path) {
+            exit_with_error(&format!("Can not write the recording {}: {}", path, err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_pocket() {
+        let terrain =
+            map::parse("% edges solid\n@..*.\n...**\n").unwrap_or_else(|err| panic!("{}", err));
+        let pocket = 4;
+        let mut game = Game::new(io::empty(), io::sink(), terrain, 1, Curve::classic());
+        for pos in 0..game.map.len() {
+            if game.map[pos] == object::SPACE && pos != pocket {
+                assert!(!game.board_full());
+                game.put(pos, object::SNAKE_BODY);
+            }
+        }
+        assert_eq!(game.map[pocket], object::SPACE);
+        assert!(game.board_full());
+    }
 }
@@ -0,0 +1,510 @@
+//! Snake maps: parsing the text format, and checking a map is playable
+//! before the terminal is switched to raw mode.
+
+use std::collections::{HashMap, VecDeque};
+use std::error;
+use std::fmt;
+
+use super::{object, Key};
+
+pub const SPACE_MARK: char = '.';
+pub const BARRIER_MARK: char = '*';
+pub const SPAWN_MARK: char = '@';
+pub const FOOD_MARK: char = 'o';
+pub const ONE_WAY_MARK: char = '#';
+// Lines starting with this set map options, e.g. "% direction up".
+pub const DIRECTIVE_MARK: char = '%';
+
+/// A parsed map: the terrain every game starts from, and where the snake and
+/// the fixed food go.
+#[derive(Clone)]
+pub struct Map {
+    pub cells: Vec<object::Object>,
+    pub cols: usize,
+    // Where the snake starts and which way it heads first, random if unset.
+    pub spawn: Option<usize>,
+    pub direction: Option<Key>,
+    // Each portal cell with its label and the cell of its pair.
+    pub portals: HashMap<usize, (char, usize)>,
+    pub foods: Vec<usize>,
+    // The only direction one-way walls let the snake through.
+    pub one_way: Key,
+    pub edges: Edges,
+    // The file line of every row, for errors found after parsing.
+    lines: Vec<usize>,
+}
+
+/// What happens to the snake at the edges of the map: it comes back on the
+/// other side where they wrap, and runs into them where they are solid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edges {
+    Wrap,
+    Solid,
+    // Only the left and right edges wrap, along the rows.
+    WrapRows,
+    // Only the top and bottom edges wrap, along the columns.
+    WrapCols,
+}
+
+impl Edges {
+    pub fn parse(s: &str) -> Option<Edges> {
+        match s {
+            "wrap" => Some(Edges::Wrap),
+            "solid" => Some(Edges::Solid),
+            "wrap-rows" => Some(Edges::WrapRows),
+            "wrap-cols" => Some(Edges::WrapCols),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Edges::Wrap => "wrap",
+            Edges::Solid => "solid",
+            Edges::WrapRows => "wrap-rows",
+            Edges::WrapCols => "wrap-cols",
+        }
+    }
+
+    pub fn wraps_rows(self) -> bool {
+        self == Edges::Wrap || self == Edges::WrapRows
+    }
+
+    pub fn wraps_cols(self) -> bool {
+        self == Edges::Wrap || self == Edges::WrapCols
+    }
+}
+
+/// Lines and columns count from 1, columns in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    NotUtf8 {
+        line: usize,
+    },
+    Empty,
+    Width {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    Mark {
+        line: usize,
+        col: usize,
+        mark: char,
+    },
+    Directive {
+        line: usize,
+        text: String,
+    },
+    SecondSpawn {
+        line: usize,
+        col: usize,
+    },
+    Portal {
+        label: char,
+        ends: usize,
+    },
+    // The snake would start on something other than an empty cell.
+    SpawnBlocked {
+        line: usize,
+        col: usize,
+    },
+    // The snake can not reach a single empty cell from its start.
+    Unreachable {
+        line: usize,
+        col: usize,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::NotUtf8 { line } => write!(f, "line {}: not valid UTF-8", line),
+            MapError::Empty => write!(f, "the map has no rows"),
+            MapError::Width {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} columns like the rows above, found {}",
+                line, expected, found
+            ),
+            MapError::Mark { line, col, mark } => write!(
+                f,
+                "line {}, column {}: unknown mark {:?}, expected one of \"{}{}{}{}{}\", a digit or a capital letter",
+                line, col, mark, SPACE_MARK, BARRIER_MARK, SPAWN_MARK, FOOD_MARK, ONE_WAY_MARK
+            ),
+            MapError::Directive { line, text } => write!(
+                f,
+                "line {}: bad directive {:?}, expected \"% direction|oneway up|right|down|left\" or \"% edges wrap|solid|wrap-rows|wrap-cols\"",
+                line, text
+            ),
+            MapError::SecondSpawn { line, col } => {
+                write!(f, "line {}, column {}: a second spawn point", line, col)
+            }
+            MapError::Portal { label, ends } => write!(
+                f,
+                "portal {:?} has {} end{}, expected 2",
+                label,
+                ends,
+                if *ends == 1 { "" } else { "s" }
+            ),
+            MapError::SpawnBlocked { line, col } => write!(
+                f,
+                "line {}, column {}: the snake starts on a cell that is not empty",
+                line, col
+            ),
+            MapError::Unreachable { line, col } => write!(
+                f,
+                "line {}, column {}: the snake starts boxed in, with no empty cell to reach",
+                line, col
+            ),
+        }
+    }
+}
+
+impl error::Error for MapError {}
+
+/// Decodes a map file, pointing at the first line that is not UTF-8.
+pub fn decode(content: Vec<u8>) -> Result<String, MapError> {
+    String::from_utf8(content).map_err(|err| {
+        let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+        MapError::NotUtf8 {
+            line: valid.iter().filter(|b| **b == b'\n').count() + 1,
+        }
+    })
+}
+
+pub fn parse(content: &str) -> Result<Map, MapError> {
+    let mut cells = Vec::new();
+    let mut cols = 0usize;
+    let mut spawn = None;
+    let mut direction = None;
+    let mut one_way = Key::Right;
+    let mut edges = Edges::Wrap;
+    let mut foods = Vec::new();
+    let mut portal_ends: HashMap<char, Vec<usize>> = HashMap::new();
+    // The file line of every row, for errors found after parsing.
+    let mut lines = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let lineno = i + 1;
+        if let Some(directive) = line.strip_prefix(DIRECTIVE_MARK) {
+            let words: Vec<&str> = directive.split_whitespace().collect();
+            match words.as_slice() {
+                ["direction", dir] if Key::parse_direction(dir).is_some() => {
+                    direction = Key::parse_direction(dir)
+                }
+                ["oneway", dir] if Key::parse_direction(dir).is_some() => {
+                    one_way = Key::parse_direction(dir).unwrap()
+                }
+                ["edges", mode] if Edges::parse(mode).is_some() => {
+                    edges = Edges::parse(mode).unwrap()
+                }
+                _ => {
+                    return Err(MapError::Directive {
+                        line: lineno,
+                        text: line.to_string(),
+                    })
+                }
+            }
+            continue;
+        }
+        // Blank lines before the first row are not rows.
+        if cols == 0 && line.is_empty() {
+            continue;
+        }
+        let width = line.chars().count();
+        if cols != 0 && width != cols {
+            return Err(MapError::Width {
+                line: lineno,
+                expected: cols,
+                found: width,
+            });
+        }
+        cols = width;
+        lines.push(lineno);
+        for (col, c) in line.chars().enumerate() {
+            let obj = match c {
+                SPACE_MARK => object::SPACE,
+                BARRIER_MARK => object::BARRIER,
+                ONE_WAY_MARK => object::ONE_WAY,
+                SPAWN_MARK => {
+                    if spawn.is_some() {
+                        return Err(MapError::SecondSpawn {
+                            line: lineno,
+                            col: col + 1,
+                        });
+                    }
+                    spawn = Some(cells.len());
+                    object::SPACE
+                }
+                FOOD_MARK => {
+                    foods.push(cells.len());
+                    object::SPACE
+                }
+                '0'..='9' | 'A'..='Z' => {
+                    portal_ends.entry(c).or_default().push(cells.len());
+                    object::PORTAL
+                }
+                _ => {
+                    return Err(MapError::Mark {
+                        line: lineno,
+                        col: col + 1,
+                        mark: c,
+                    })
+                }
+            };
+            cells.push(obj);
+        }
+    }
+    if cells.is_empty() {
+        return Err(MapError::Empty);
+    }
+
+    let mut portals = HashMap::new();
+    let mut labels: Vec<&char> = portal_ends.keys().collect();
+    labels.sort();
+    for label in labels {
+        let ends = &portal_ends[label];
+        if ends.len() != 2 {
+            return Err(MapError::Portal {
+                label: *label,
+                ends: ends.len(),
+            });
+        }
+        portals.insert(ends[0], (*label, ends[1]));
+        portals.insert(ends[1], (*label, ends[0]));
+    }
+
+    let map = Map {
+        cells,
+        cols,
+        spawn,
+        direction,
+        portals,
+        foods,
+        one_way,
+        edges,
+        lines,
+    };
+    map.validate()?;
+    Ok(map)
+}
+
+impl Map {
+    /// Checks the snake has somewhere to go from its start, which depends on
+    /// the edges; run it again after changing them.
+    pub fn validate(&self) -> Result<(), MapError> {
+        let start = self.start();
+        let (line, col) = (self.lines[start / self.cols], start % self.cols + 1);
+        if self.cells[start] != object::SPACE || self.foods.contains(&start) {
+            return Err(MapError::SpawnBlocked { line, col });
+        }
+        if !self.has_room(start) {
+            return Err(MapError::Unreachable { line, col });
+        }
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len() / self.cols
+    }
+
+    /// Where the snake starts: the spawn point, or the middle of the map.
+    pub fn start(&self) -> usize {
+        self.spawn.unwrap_or(self.cells.len() / 2)
+    }
+
+    /// The cell next to `pos` towards `dir`, across the edge if it wraps;
+    /// `None` off a solid edge.
+    pub fn neighbour(&self, pos: usize, dir: Key) -> Option<usize> {
+        let (rows, cols) = (self.rows(), self.cols);
+        let (row, col) = (pos / cols, pos % cols);
+        let (row, col) = match dir {
+            Key::Up if row > 0 => (row - 1, col),
+            Key::Up if self.edges.wraps_cols() => (rows - 1, col),
+            Key::Down if row + 1 < rows => (row + 1, col),
+            Key::Down if self.edges.wraps_cols() => (0, col),
+            Key::Left if col > 0 => (row, col - 1),
+            Key::Left if self.edges.wraps_rows() => (row, cols - 1),
+            Key::Right if col + 1 < cols => (row, col + 1),
+            Key::Right if self.edges.wraps_rows() => (row, 0),
+            _ => return None,
+        };
+        Some(row * cols + col)
+    }
+
+    /// Where the head lands moving from `pos` towards `dir`: the next cell,
+    /// or the cell past the other end if that is a portal. `None` off a
+    /// solid edge.
+    pub fn target(&self, pos: usize, dir: Key) -> Option<usize> {
+        let next = self.neighbour(pos, dir)?;
+        match self.portals.get(&next) {
+            Some(&(_, pair)) => self.neighbour(pair, dir),
+            None => Some(next),
+        }
+    }
+
+    /// Steps between two cells with no walls in the way, across the edges
+    /// that wrap.
+    pub fn distance(&self, a: usize, b: usize) -> usize {
+        let (rows, cols) = (self.rows(), self.cols);
+        let (dr, dc) = ((a / cols).abs_diff(b / cols), (a % cols).abs_diff(b % cols));
+        let dr = if self.edges.wraps_cols() {
+            dr.min(rows - dr)
+        } else {
+            dr
+        };
+        let dc = if self.edges.wraps_rows() {
+            dc.min(cols - dc)
+        } else {
+            dc
+        };
+        dr + dc
+    }
+
+    /// The cells the snake can get to from `start`, through empty cells and
+    /// the right way through one-way walls.
+    pub fn reachable(&self, start: usize) -> Vec<bool> {
+        let mut seen = vec![false; self.cells.len()];
+        let mut queue = VecDeque::new();
+        seen[start] = true;
+        queue.push_back(start);
+        while let Some(pos) = queue.pop_front() {
+            for dir in [Key::Up, Key::Right, Key::Down, Key::Left].iter() {
+                let next = match self.target(pos, *dir) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                let passable = match self.cells[next] {
+                    object::SPACE => true,
+                    object::ONE_WAY => *dir == self.one_way,
+                    _ => false,
+                };
+                if passable && !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen
+    }
+
+    // Whether the snake can get from `start` to any other empty cell.
+    fn has_room(&self, start: usize) -> bool {
+        self.reachable(start)
+            .iter()
+            .enumerate()
+            .any(|(pos, seen)| *seen && pos != start && self.cells[pos] == object::SPACE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ragged_row() {
+        assert_eq!(
+            parse("....\n...\n").err(),
+            Some(MapError::Width {
+                line: 2,
+                expected: 4,
+                found: 3
+            })
+        );
+        // Directives do not count as rows.
+        assert_eq!(
+            parse("...\n% edges solid\n..\n").err(),
+            Some(MapError::Width {
+                line: 3,
+                expected: 3,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn leading_blank_lines() {
+        assert_eq!(
+            parse("\n% edges solid\n\n@*.\n**.\n").err(),
+            Some(MapError::Unreachable { line: 4, col: 1 })
+        );
+        assert_eq!(
+            parse("\n...\n\n...\n").err(),
+            Some(MapError::Width {
+                line: 3,
+                expected: 3,
+                found: 0
+            })
+        );
+        let map = parse("\n\n.@.\n").unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!((map.rows(), map.cols), (1, 3));
+    }
+
+    #[test]
+    fn unknown_mark() {
+        assert_eq!(
+            parse("...\n.?.\n...\n").err(),
+            Some(MapError::Mark {
+                line: 2,
+                col: 2,
+                mark: '?'
+            })
+        );
+    }
+
+    #[test]
+    fn second_spawn() {
+        assert_eq!(
+            parse("@..\n...\n..@\n").err(),
+            Some(MapError::SecondSpawn { line: 3, col: 3 })
+        );
+    }
+
+    #[test]
+    fn spawn_blocked() {
+        // Without a spawn point the snake starts in the middle.
+        assert_eq!(
+            parse("...\n.*.\n...\n").err(),
+            Some(MapError::SpawnBlocked { line: 2, col: 2 })
+        );
+    }
+
+    #[test]
+    fn unreachable_start() {
+        assert_eq!(
+            parse("% edges solid\n@*.\n**.\n").err(),
+            Some(MapError::Unreachable { line: 2, col: 1 })
+        );
+    }
+
+    #[test]
+    fn bad_directive() {
+        assert_eq!(
+            parse("...\n% edges sideways\n").err(),
+            Some(MapError::Directive {
+                line: 2,
+                text: "% edges sideways".to_string()
+            })
+        );
+        assert_eq!(
+            parse("% speed 3\n...\n").err(),
+            Some(MapError::Directive {
+                line: 1,
+                text: "% speed 3".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn valid_map() {
+        let map = parse("% direction up\n.@.\n.o.\n").unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!((map.rows(), map.cols), (2, 3));
+        assert_eq!(map.start(), 1);
+        assert_eq!(map.direction, Some(Key::Up));
+        assert_eq!(map.foods, vec![4]);
+    }
+}
@@ -0,0 +1,306 @@
+//! Snakes the computer steers, after the same food as the player. Each one
+//! follows a strategy, slips now and then by its skill, and may come back
+//! some ticks after it dies.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use game_rs::rng::SplitMix64;
+use rand::Rng;
+
+use super::map::Map;
+use super::{object, passable, room, Key, Snake, DIRECTIONS};
+
+pub const MAX_RIVALS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    // Heads for the nearest food as the crow flies.
+    Greedy,
+    // Takes the shortest way to food, unless it leads somewhere too tight
+    // for the snake to fit.
+    Bfs,
+    // Wanders.
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skill {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// When a dead rival comes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Respawn {
+    Never,
+    // After this many ticks.
+    After(u64),
+}
+
+/// The rivals of a game: how many, the strategies they take in turn, how
+/// well they play and whether they come back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rivals {
+    pub count: usize,
+    pub strategies: Vec<Strategy>,
+    pub skill: Skill,
+    pub respawn: Respawn,
+}
+
+impl Strategy {
+    pub fn parse(s: &str) -> Option<Strategy> {
+        match s {
+            "greedy" => Some(Strategy::Greedy),
+            "bfs" => Some(Strategy::Bfs),
+            "random" => Some(Strategy::Random),
+            _ => None,
+        }
+    }
+
+    /// Parses a comma separated list of strategies.
+    pub fn parse_list(s: &str) -> Option<Vec<Strategy>> {
+        s.split(',')
+            .map(|name| Strategy::parse(name.trim()))
+            .collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Greedy => "greedy",
+            Strategy::Bfs => "bfs",
+            Strategy::Random => "random",
+        }
+    }
+
+    /// What the head and the body of a rival with this strategy are on the
+    /// map.
+    pub fn looks(self) -> (object::Object, object::Object) {
+        match self {
+            Strategy::Greedy => (object::GREEDY_HEAD, object::GREEDY_BODY),
+            Strategy::Bfs => (object::BFS_HEAD, object::BFS_BODY),
+            Strategy::Random => (object::RANDOM_HEAD, object::RANDOM_BODY),
+        }
+    }
+
+    /// The way `snake` goes next on `cells`.
+    pub fn steer(
+        self,
+        skill: Skill,
+        (terrain, cells): (&Map, &[object::Object]),
+        snake: &Snake,
+        rng: &mut SplitMix64,
+    ) -> Key {
+        let head = snake.body[0];
+        // A slip goes on the way it was going, whatever is there.
+        if rng.gen_range(0, 100) < skill.slip() {
+            return snake.heading;
+        }
+        let open: Vec<Key> = DIRECTIONS
+            .iter()
+            .cloned()
+            .filter(|dir| passable(terrain, cells, head, *dir).is_some())
+            .collect();
+        if open.is_empty() {
+            return snake.heading;
+        }
+        match self {
+            Strategy::Greedy => greedy(terrain, cells, head, snake.heading, &open),
+            Strategy::Bfs => bfs(terrain, cells, snake, &open),
+            Strategy::Random => {
+                if open.contains(&snake.heading) && rng.gen_range(0, 4) != 0 {
+                    snake.heading
+                } else {
+                    open[rng.gen_range(0, open.len())]
+                }
+            }
+        }
+    }
+}
+
+impl Skill {
+    pub fn parse(s: &str) -> Option<Skill> {
+        match s {
+            "easy" => Some(Skill::Easy),
+            "normal" => Some(Skill::Normal),
+            "hard" => Some(Skill::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Skill::Easy => "easy",
+            Skill::Normal => "normal",
+            Skill::Hard => "hard",
+        }
+    }
+
+    // How often in a hundred ticks the rival forgets to steer.
+    fn slip(self) -> u32 {
+        match self {
+            Skill::Easy => 25,
+            Skill::Normal => 8,
+            Skill::Hard => 0,
+        }
+    }
+}
+
+impl Respawn {
+    /// Parses `never` or a number of ticks.
+    pub fn parse(s: &str) -> Option<Respawn> {
+        match s {
+            "never" => Some(Respawn::Never),
+            _ => s.parse::<u64>().ok().map(Respawn::After),
+        }
+    }
+}
+
+impl fmt::Display for Respawn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Respawn::Never => write!(f, "never"),
+            Respawn::After(ticks) => write!(f, "{}", ticks),
+        }
+    }
+}
+
+impl Rivals {
+    pub fn none() -> Rivals {
+        Rivals {
+            count: 0,
+            strategies: vec![Strategy::Greedy],
+            skill: Skill::Normal,
+            respawn: Respawn::After(30),
+        }
+    }
+
+    /// Parses what `Display` writes, as replays keep it:
+    /// `COUNT STRATEGY[,STRATEGY...] SKILL RESPAWN`.
+    pub fn parse(s: &str) -> Option<Rivals> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.as_slice() {
+            [count, strategies, skill, respawn] => {
+                let count = count.parse::<usize>().ok()?;
+                if count > MAX_RIVALS {
+                    return None;
+                }
+                Some(Rivals {
+                    count,
+                    strategies: Strategy::parse_list(strategies)?,
+                    skill: Skill::parse(skill)?,
+                    respawn: Respawn::parse(respawn)?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The strategy of the `i`th rival.
+    pub fn strategy(&self, i: usize) -> Strategy {
+        self.strategies[i % self.strategies.len()]
+    }
+}
+
+impl fmt::Display for Rivals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let strategies: Vec<&str> = self.strategies.iter().map(|s| s.name()).collect();
+        write!(
+            f,
+            "{} {} {} {}",
+            self.count,
+            strategies.join(","),
+            self.skill.name(),
+            self.respawn
+        )
+    }
+}
+
+// The open way that brings the head closest to the nearest food, straight
+// on when it is as good as a turn.
+fn greedy(terrain: &Map, cells: &[object::Object], head: usize, heading: Key, open: &[Key]) -> Key {
+    let cols = terrain.cols;
+    let distance =
+        |a: usize, b: usize| (a / cols).abs_diff(b / cols) + (a % cols).abs_diff(b % cols);
+    let food = (0..cells.len())
+        .filter(|pos| object::is_food(cells[*pos]))
+        .min_by_key(|pos| distance(head, *pos));
+    let food = match food {
+        Some(food) => food,
+        None if open.contains(&heading) => return heading,
+        None => return open[0],
+    };
+    let mut best = if open.contains(&heading) {
+        heading
+    } else {
+        open[0]
+    };
+    let mut best_distance = usize::MAX;
+    for dir in open.iter() {
+        let next = passable(terrain, cells, head, *dir).unwrap();
+        let d = distance(next, food);
+        if d < best_distance || (d == best_distance && *dir == heading) {
+            best = *dir;
+            best_distance = d;
+        }
+    }
+    best
+}
+
+// The first step of the shortest way to food, if the cell it leads to has
+// room for the snake; otherwise the open way with the most room.
+fn bfs(terrain: &Map, cells: &[object::Object], snake: &Snake, open: &[Key]) -> Key {
+    let head = snake.body[0];
+    // The first step of the way to every cell reached.
+    let mut first: Vec<Option<Key>> = vec![None; cells.len()];
+    let mut queue = VecDeque::new();
+    for dir in open.iter() {
+        let next = passable(terrain, cells, head, *dir).unwrap();
+        if first[next].is_none() {
+            first[next] = Some(*dir);
+            queue.push_back(next);
+        }
+    }
+    let mut way = None;
+    while let Some(pos) = queue.pop_front() {
+        if object::is_food(cells[pos]) {
+            way = first[pos];
+            break;
+        }
+        for dir in DIRECTIONS.iter() {
+            match passable(terrain, cells, pos, *dir) {
+                Some(next) if next != head && first[next].is_none() => {
+                    first[next] = first[pos];
+                    queue.push_back(next);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let need = snake.body.len() + 1;
+    let room_after = |dir: Key| {
+        room(
+            terrain,
+            cells,
+            passable(terrain, cells, head, dir).unwrap(),
+            need,
+        )
+    };
+    if let Some(dir) = way {
+        if room_after(dir) >= need {
+            return dir;
+        }
+    }
+    let mut best = open[0];
+    let mut best_room = 0;
+    for dir in open.iter() {
+        let room = room_after(*dir);
+        if room > best_room || (room == best_room && *dir == snake.heading) {
+            best = *dir;
+            best_room = room;
+        }
+    }
+    best
+}
@@ -0,0 +1,244 @@
+//! What the terminal shows of the game, kept from one frame to the next so a
+//! tick only writes the cells that changed: the head, the tail it left, new
+//! food. The whole screen is drawn again when the terminal is resized, the
+//! map changes size or edges, or on `Ctrl-l`.
+
+use std::io::Write;
+
+use termion::{clear, color, cursor, style};
+
+use super::map::{Edges, Map};
+use super::{draw_board, header_line, object};
+
+// The widest line of a box over the map at least is.
+const OVERLAY_WIDTH: usize = 15;
+
+pub struct Screen {
+    // What each cell shows, and its glyph, also under the box over the map.
+    cells: Vec<(object::Object, char)>,
+    cols: usize,
+    edges: Option<Edges>,
+    header: (String, String),
+    legend: String,
+    // The column the box over the map starts at and its lines.
+    overlay: Option<(u16, Vec<String>)>,
+    size: Option<(u16, u16)>,
+    // Whether the next frame is drawn from scratch.
+    stale: bool,
+}
+
+// Where the cursor is and the color it paints with, to leave out the escapes
+// that change neither.
+struct Brush {
+    at: Option<(u16, u16)>,
+    bg: Option<object::Object>,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen {
+            cells: Vec::new(),
+            cols: 0,
+            edges: None,
+            header: (String::new(), String::new()),
+            legend: String::new(),
+            overlay: None,
+            size: None,
+            stale: true,
+        }
+    }
+
+    /// Has the next frame drawn from scratch.
+    pub fn refresh(&mut self) {
+        self.stale = true;
+    }
+
+    /// Draws a frame: the header, the cells of the map with `glyph` over
+    /// them and the legend under it, writing only what changed since the
+    /// last one.
+    pub fn draw<W: Write>(
+        &mut self,
+        stdout: &mut W,
+        (left, right): (&str, &str),
+        (terrain, cells): (&Map, &[object::Object]),
+        glyph: &dyn Fn(usize) -> char,
+        legend: &str,
+    ) {
+        let cols = terrain.cols;
+        let size = termion::terminal_size().ok();
+        if self.stale
+            || size != self.size
+            || cols != self.cols
+            || cells.len() != self.cells.len()
+            || Some(terrain.edges) != self.edges
+        {
+            draw_board(
+                stdout,
+                (left, right),
+                cells,
+                cols,
+                terrain.edges,
+                glyph,
+                None,
+            );
+            write!(stdout, "{}\n\r", legend).unwrap();
+            stdout.flush().unwrap();
+            self.cells = (0..cells.len())
+                .map(|pos| (cells[pos], glyph(pos)))
+                .collect();
+            self.cols = cols;
+            self.edges = Some(terrain.edges);
+            self.header = (left.to_string(), right.to_string());
+            self.legend = legend.to_string();
+            // The screen was cleared from under the box.
+            self.overlay = None;
+            self.size = size;
+            self.stale = false;
+            return;
+        }
+
+        let mut out = String::new();
+        if self.header.0 != left || self.header.1 != right {
+            out.push_str(&format!(
+                "{}{}",
+                cursor::Goto(1, 2),
+                header_line(left, right, (cols + 2) * 2)
+            ));
+            self.header = (left.to_string(), right.to_string());
+        }
+        let mut brush = Brush { at: None, bg: None };
+        for (pos, obj) in cells.iter().enumerate() {
+            let cell = (*obj, glyph(pos));
+            if self.cells[pos] != cell {
+                self.cells[pos] = cell;
+                if !self.covers(pos) {
+                    self.paint(&mut out, &mut brush, pos);
+                }
+            }
+        }
+        if brush.bg.is_some() {
+            out.push_str(style::Reset.as_ref());
+        }
+        if self.legend != legend {
+            let row = (cells.len() / cols + 5) as u16;
+            out.push_str(&format!(
+                "{}{}{}\n\r",
+                cursor::Goto(1, row),
+                clear::CurrentLine,
+                legend
+            ));
+            self.legend = legend.to_string();
+        }
+        if !out.is_empty() {
+            stdout.write_all(out.as_bytes()).unwrap();
+            stdout.flush().unwrap();
+        }
+    }
+
+    /// Draws a box over the map, the first line in bold, unless it is
+    /// already there.
+    pub fn overlay<W: Write>(&mut self, stdout: &mut W, start: u16, lines: &[String]) {
+        if let Some((shown_start, ref shown)) = self.overlay {
+            if shown_start == start && shown.as_slice() == lines {
+                return;
+            }
+        }
+        self.uncover(stdout);
+        let bg = color::Bg(color::Rgb(128, 128, 128));
+        for (i, line) in lines.iter().enumerate() {
+            let bold = if i == 0 {
+                style::Bold.to_string()
+            } else {
+                String::new()
+            };
+            write!(
+                stdout,
+                "{}{}{}{:width$}{}\n\r",
+                cursor::Goto(start, 4 + i as u16),
+                bg,
+                bold,
+                line,
+                style::Reset,
+                width = OVERLAY_WIDTH
+            )
+            .unwrap();
+        }
+        stdout.flush().unwrap();
+        self.overlay = Some((start, lines.to_vec()));
+    }
+
+    /// Takes the box off the map, drawing the cells under it again unless
+    /// the next frame is drawn from scratch anyway, as it is after a box
+    /// that ran off the cells.
+    pub fn uncover<W: Write>(&mut self, stdout: &mut W) {
+        let (start, lines) = match self.overlay.take() {
+            Some(overlay) => overlay,
+            None => return,
+        };
+        let (left, right) = overlay_span(start, &lines);
+        if self.stale
+            || lines.len() > self.cells.len() / self.cols
+            || left < 3
+            || right > 2 + self.cols * 2
+        {
+            self.stale = true;
+            return;
+        }
+        let mut out = String::new();
+        let mut brush = Brush { at: None, bg: None };
+        for row in 0..lines.len() {
+            for col in 0..self.cols {
+                let x = 3 + col * 2;
+                if x + 1 >= left && x <= right {
+                    self.paint(&mut out, &mut brush, row * self.cols + col);
+                }
+            }
+        }
+        out.push_str(style::Reset.as_ref());
+        stdout.write_all(out.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    // Whether the box over the map hides a cell.
+    fn covers(&self, pos: usize) -> bool {
+        match self.overlay {
+            Some((start, ref lines)) => {
+                let (left, right) = overlay_span(start, lines);
+                let x = 3 + (pos % self.cols) * 2;
+                pos / self.cols < lines.len() && x + 1 >= left && x <= right
+            }
+            None => false,
+        }
+    }
+
+    // Writes a cell as it is kept, two columns wide.
+    fn paint(&self, out: &mut String, brush: &mut Brush, pos: usize) {
+        let (obj, glyph) = self.cells[pos];
+        let at = (
+            (3 + (pos % self.cols) * 2) as u16,
+            (4 + pos / self.cols) as u16,
+        );
+        if brush.at != Some(at) {
+            out.push_str(&cursor::Goto(at.0, at.1).to_string());
+        }
+        if brush.bg != Some(obj) {
+            out.push_str(&object::COLORS[obj as usize].to_string());
+        }
+        out.push_str(&format!("{:2}", glyph));
+        *brush = Brush {
+            at: Some((at.0 + 2, at.1)),
+            bg: Some(obj),
+        };
+    }
+}
+
+// The first and the last terminal column a box starting at `start` takes.
+fn overlay_span(start: u16, lines: &[String]) -> (usize, usize) {
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(OVERLAY_WIDTH);
+    (start as usize, start as usize + width - 1)
+}
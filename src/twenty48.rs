@@ -0,0 +1,363 @@
+//! The 2048 engine: board state, sliding and merging, tile spawns and scoring.
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+pub const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// The outcome of a slide that moved at least one tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slide {
+    /// Points scored by the merges of this slide.
+    pub gained: u32,
+    /// Cell indexes of the tiles produced by a merge.
+    pub merged: Vec<usize>,
+}
+
+/// A tile dropped onto an empty cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spawn {
+    pub index: usize,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    score: u32,
+    moves: u32,
+    cells: Vec<u32>,
+}
+
+impl Board {
+    /// An empty board; call `reset` to drop the two starting tiles.
+    pub fn new(width: usize, height: usize) -> Self {
+        Board {
+            width,
+            height,
+            score: 0,
+            moves: 0,
+            cells: vec![0; width * height],
+        }
+    }
+
+    /// Rebuilds a board from its parts, e.g. a saved game. Returns `None` if
+    /// the cells do not fit the size or hold something other than powers of two.
+    pub fn from_cells(
+        width: usize,
+        height: usize,
+        cells: Vec<u32>,
+        score: u32,
+        moves: u32,
+    ) -> Option<Self> {
+        if cells.len() != width * height
+            || cells
+                .iter()
+                .any(|v| *v == 1 || !(*v == 0 || v.is_power_of_two()))
+        {
+            return None;
+        }
+        Some(Board {
+            width,
+            height,
+            score,
+            moves,
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// The number of slides that moved something.
+    pub fn moves(&self) -> u32 {
+        self.moves
+    }
+
+    /// Row-major tiles, 0 for an empty cell.
+    pub fn cells(&self) -> &[u32] {
+        &self.cells
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> u32 {
+        self.cells[row * self.width + col]
+    }
+
+    /// Overwrites a single cell without touching the score, for setting up
+    /// positions and for searches over possible spawns.
+    pub fn set(&mut self, index: usize, value: u32) {
+        self.cells[index] = value;
+    }
+
+    pub fn max_tile(&self) -> u32 {
+        *self.cells.iter().max().unwrap_or(&0)
+    }
+
+    pub fn holes(&self) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|i| self.cells[*i] == 0)
+            .collect()
+    }
+
+    /// Clears the board and drops two 2s on distinct random cells.
+    pub fn reset<R: Rng>(&mut self, rng: &mut R) -> [Spawn; 2] {
+        for cell in self.cells.iter_mut() {
+            *cell = 0;
+        }
+        self.score = 0;
+        self.moves = 0;
+
+        let len = self.cells.len();
+        let first = rng.gen_range(0, len);
+        let mut second = rng.gen_range(0, len);
+        while second == first {
+            second = rng.gen_range(0, len);
+        }
+        self.cells[first] = 2;
+        self.cells[second] = 2;
+        [
+            Spawn {
+                index: first,
+                value: 2,
+            },
+            Spawn {
+                index: second,
+                value: 2,
+            },
+        ]
+    }
+
+    // The cell index of the `k`th tile of a line, counted from the edge the
+    // tiles slide to.
+    fn index(&self, dir: Direction, line: usize, k: usize) -> usize {
+        let (w, h) = (self.width, self.height);
+        match dir {
+            Direction::Left => line * w + k,
+            Direction::Right => line * w + (w - 1 - k),
+            Direction::Up => k * w + line,
+            Direction::Down => (h - 1 - k) * w + line,
+        }
+    }
+
+    fn lines(&self, dir: Direction) -> (usize, usize) {
+        match dir {
+            Direction::Left | Direction::Right => (self.height, self.width),
+            Direction::Up | Direction::Down => (self.width, self.height),
+        }
+    }
+
+    /// Slides every tile towards `dir`, merging equal neighbours once per
+    /// slide. Returns `None`, leaving the board untouched, if nothing moved.
+    pub fn slide(&mut self, dir: Direction) -> Option<Slide> {
+        let (lines, len) = self.lines(dir);
+        let mut moved = false;
+        let mut result = Slide {
+            gained: 0,
+            merged: Vec::new(),
+        };
+
+        for line in 0..lines {
+            let mut next = 0;
+            let mut mergeable = false;
+            for k in 0..len {
+                let idx = self.index(dir, line, k);
+                let val = self.cells[idx];
+                if val == 0 {
+                    continue;
+                }
+                self.cells[idx] = 0;
+
+                let prev = if next > 0 {
+                    self.index(dir, line, next - 1)
+                } else {
+                    0
+                };
+                if mergeable && self.cells[prev] == val {
+                    self.cells[prev] = val * 2;
+                    result.gained += val * 2;
+                    result.merged.push(prev);
+                    mergeable = false;
+                    moved = true;
+                } else {
+                    let to = self.index(dir, line, next);
+                    self.cells[to] = val;
+                    moved |= to != idx;
+                    mergeable = true;
+                    next += 1;
+                }
+            }
+        }
+
+        if !moved {
+            return None;
+        }
+        self.score += result.gained;
+        self.moves += 1;
+        Some(result)
+    }
+
+    /// Drops a 2 or, less often, a 4 on a random empty cell. Returns `None`
+    /// if the board is full.
+    pub fn spawn<R: Rng>(&mut self, rng: &mut R) -> Option<Spawn> {
+        let holes = self.holes();
+        if holes.is_empty() {
+            return None;
+        }
+        let index = holes[rng.gen_range(0, holes.len())];
+        let value = if rng.gen::<u8>() <= spawn_threshold(holes.len()) {
+            2
+        } else {
+            4
+        };
+        self.cells[index] = value;
+        Some(Spawn { index, value })
+    }
+
+    /// True if the board is full and no two neighbours are equal.
+    pub fn is_over(&self) -> bool {
+        if self.cells.contains(&0) {
+            return false;
+        }
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let val = self.get(row, col);
+                if col + 1 < self.width && self.get(row, col + 1) == val {
+                    return false;
+                }
+                if row + 1 < self.height && self.get(row + 1, col) == val {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+// A spawned tile is a 2 if a random byte is at most this; crowded boards get
+// more 4s.
+fn spawn_threshold(holes: usize) -> u8 {
+    if holes <= 4 {
+        127
+    } else {
+        222
+    }
+}
+
+/// The chance that `Board::spawn` drops a 2 when the board has `holes` empty cells.
+pub fn two_probability(holes: usize) -> f64 {
+    (spawn_threshold(holes) as f64 + 1.0) / 256.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rng::SplitMix64;
+
+    fn board(width: usize, height: usize, cells: &[u32]) -> Board {
+        Board::from_cells(width, height, cells.to_vec(), 0, 0).unwrap()
+    }
+
+    #[test]
+    fn slide_merges_pairs_once() {
+        let mut b = board(4, 1, &[2, 2, 2, 2]);
+        let slide = b.slide(Direction::Left).unwrap();
+        assert_eq!(b.cells(), &[4, 4, 0, 0]);
+        assert_eq!(slide.gained, 8);
+        assert_eq!(slide.merged, vec![0, 1]);
+        assert_eq!(b.score(), 8);
+        assert_eq!(b.moves(), 1);
+    }
+
+    #[test]
+    fn merged_tile_does_not_merge_again() {
+        let mut b = board(4, 1, &[4, 2, 2, 0]);
+        let slide = b.slide(Direction::Left).unwrap();
+        assert_eq!(b.cells(), &[4, 4, 0, 0]);
+        assert_eq!(slide.gained, 4);
+
+        let mut b = board(4, 1, &[2, 2, 4, 8]);
+        b.slide(Direction::Right).unwrap();
+        assert_eq!(b.cells(), &[0, 4, 4, 8]);
+    }
+
+    #[test]
+    fn slide_without_movement_leaves_board_untouched() {
+        let mut b = board(2, 2, &[2, 4, 4, 2]);
+        let before = b.clone();
+        for dir in DIRECTIONS.iter() {
+            assert_eq!(b.slide(*dir), None);
+            assert_eq!(b, before);
+        }
+    }
+
+    #[test]
+    fn spawn_on_full_board() {
+        let mut b = board(2, 2, &[2, 4, 8, 16]);
+        let mut rng = SplitMix64::new(1);
+        assert_eq!(b.spawn(&mut rng), None);
+        assert_eq!(b.cells(), &[2, 4, 8, 16]);
+    }
+
+    #[test]
+    fn spawn_fills_the_only_hole() {
+        let mut b = board(2, 2, &[2, 4, 0, 16]);
+        let spawn = b.spawn(&mut SplitMix64::new(7)).unwrap();
+        assert_eq!(spawn.index, 2);
+        assert!(spawn.value == 2 || spawn.value == 4);
+        assert_eq!(b.get(1, 0), spawn.value);
+    }
+
+    #[test]
+    fn is_over_only_when_stuck() {
+        assert!(board(2, 2, &[2, 4, 4, 2]).is_over());
+        assert!(!board(2, 2, &[2, 2, 4, 8]).is_over());
+        assert!(!board(2, 2, &[2, 4, 2, 8]).is_over());
+        assert!(!board(2, 2, &[2, 4, 0, 8]).is_over());
+    }
+
+    #[test]
+    fn rectangular_board() {
+        #[rustfmt::skip]
+        let mut b = board(4, 6, &[
+            2, 0, 0, 0,
+            2, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 4,
+            0, 0, 0, 0,
+            0, 0, 0, 4,
+        ]);
+        let slide = b.slide(Direction::Down).unwrap();
+        assert_eq!(slide.gained, 12);
+        assert_eq!(b.get(5, 0), 4);
+        assert_eq!(b.get(5, 3), 8);
+        assert_eq!(b.holes().len(), 22);
+
+        let slide = b.slide(Direction::Left).unwrap();
+        assert_eq!(slide.gained, 0);
+        assert_eq!(&b.cells()[20..], &[4, 8, 0, 0]);
+        assert_eq!(b.score(), 12);
+        assert_eq!((b.width(), b.height()), (4, 6));
+    }
+}